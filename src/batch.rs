@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+use std::f32::consts::PI;
+use std::ops::Range;
+
+use nannou::glam::{DVec2, Vec2};
+
+use crate::coloring::Paint;
+use crate::lines::Vertex;
+use crate::scene::{self, Shape};
+use crate::spatial::{Bounds, Grid};
+
+/// How far a sharp corner's miter may reach, in half-widths, before it is
+/// cut short.
+const MITER_LIMIT: f32 = 4.0;
+/// Triangles in each round cap.
+const CAP_STEPS: u32 = 4;
+/// Most segments in a chunk, so long lines can be culled piece by piece.
+const CHUNK_SEGMENTS: usize = 64;
+
+/// A run of a line's triangles that is culled as a whole.
+struct Chunk {
+    /// Around the chunk's points, not counting the line's width.
+    bounds: Bounds,
+    /// The middle and largest side of the points' bounds, kept in double
+    /// precision since deep zooms need them finer than `bounds` holds.
+    centre: DVec2,
+    size: f64,
+    width: f32,
+    indices: Range<u32>,
+    /// A square standing in for the chunk when it is smaller than a pixel.
+    dot: Range<u32>,
+}
+
+/// The parts of a batch's indices to draw.
+#[derive(Clone, Default, Debug)]
+pub struct Visible {
+    pub ranges: Vec<Range<u32>>,
+}
+
+impl Visible {
+    pub fn triangles(&self) -> usize {
+        self.ranges.iter().map(|range| range.len()).sum::<usize>() / 3
+    }
+
+    /// Adds `range`, joining it to the last one if they meet.
+    fn push(&mut self, range: Range<u32>) {
+        match self.ranges.last_mut() {
+            Some(last) if last.end == range.start => last.end = range.end,
+            _ => self.ranges.push(range),
+        }
+    }
+
+    /// The triangles from `start` up to `end`, counting through the ranges
+    /// in order.
+    pub fn slice(&self, start: usize, end: usize) -> Visible {
+        let mut slice = Visible::default();
+        let (mut first, start, end) = (0, start as u32 * 3, end as u32 * 3);
+        for range in self.ranges.iter() {
+            let last = first + range.len() as u32;
+            if last > start && first < end {
+                slice.push(range.start + start.saturating_sub(first)..range.end - last.saturating_sub(end));
+            }
+            first = last;
+        }
+        slice
+    }
+}
+
+/// Every line and curve of a figure tessellated into one triangle mesh, so it
+/// can be uploaded once and reused until the figure changes. Vertices are
+/// split into a point on the line, which zooms with the figure, and an offset
+/// from it, which doesn't, so lines keep their width in pixels at any zoom.
+/// Lines are split into chunks, indexed so that only those in view need
+/// drawing.
+#[derive(Default)]
+pub struct LineBatch {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    chunks: Vec<Chunk>,
+    grid: Option<Grid>,
+    /// The widest line, in pixels.
+    max_width: f32,
+}
+
+impl LineBatch {
+    /// Tessellates the lines and curves among `shapes`, sampling curves at
+    /// `curve_steps` points per piece. Other shapes are left out.
+    pub fn new(shapes: &[Shape], curve_steps: usize) -> LineBatch {
+        let mut batch = LineBatch::default();
+        for shape in shapes.iter() {
+            match shape {
+                Shape::Line { points, width, colors } => batch.polyline(points, colors, *width),
+                Shape::Curve { pieces, width, colors } => {
+                    let (points, colors) = scene::flatten(pieces, colors, curve_steps);
+                    batch.polyline(&points, &colors, *width);
+                }
+                Shape::Fill { .. } | Shape::Sprite { .. } => {}
+            }
+        }
+        batch.dots();
+        let bounds: Vec<Bounds> = batch.chunks.iter().map(|chunk| chunk.bounds).collect();
+        batch.grid = Some(Grid::new(&bounds));
+        batch
+    }
+
+    /// The triangles of the chunks that can be seen in `view`, given in the
+    /// figure's units, with the figure zoomed by `scale` about `centre`.
+    /// Chunks smaller than a pixel are drawn as a single dot, and only one
+    /// dot is drawn per pixel.
+    pub fn visible(&self, view: &Bounds, centre: DVec2, scale: f64) -> Visible {
+        let mut visible = Visible::default();
+        let Some(grid) = &self.grid else {
+            return visible;
+        };
+
+        // mitered corners reach past the points by up to `MITER_LIMIT` half
+        // widths, in pixels
+        let scale32 = scale as f32;
+        let margin = MITER_LIMIT * self.max_width / 2.0 / scale32;
+        let mut dots = HashSet::new();
+        for idx in grid.query(&view.grow(margin)) {
+            let chunk = &self.chunks[idx];
+            if !chunk.bounds.grow(MITER_LIMIT * chunk.width / 2.0 / scale32).overlaps(view) {
+                continue;
+            }
+            if chunk.size * scale < 1.0 {
+                let pixel = ((chunk.centre - centre) * scale).round();
+                if dots.insert((pixel.x as i64, pixel.y as i64)) {
+                    visible.push(chunk.dot.clone());
+                }
+            } else {
+                visible.push(chunk.indices.clone());
+            }
+        }
+        visible
+    }
+
+    fn vertex(&mut self, point: DVec2, offset: Vec2, color: Paint) -> u32 {
+        self.vertices.push(Vertex::new(point, offset, color));
+        self.vertices.len() as u32 - 1
+    }
+
+    /// Ends the chunk that started at `start` and begins a new one from the
+    /// last pair of vertices, which both share.
+    fn split(&mut self, start: &mut (usize, usize), bounds: &mut Bounds, width: f32) {
+        self.close(*start, *bounds, width);
+        let vertices = self.vertices.len();
+        *start = (vertices - 2, self.indices.len());
+        *bounds = Bounds::around([self.vertices[vertices - 1].point().as_f32()]);
+    }
+
+    /// Adds a chunk of everything since `start`.
+    fn close(&mut self, start: (usize, usize), bounds: Bounds, width: f32) {
+        let points = self.vertices[start.0..].iter().map(|vertex| vertex.point());
+        let (min, max) = points.fold((DVec2::splat(f64::INFINITY), DVec2::splat(f64::NEG_INFINITY)), |(min, max), p| (min.min(p), max.max(p)));
+        let (centre, size) = ((min + max) / 2.0, (max - min).max_element());
+        let indices = start.1 as u32..self.indices.len() as u32;
+        self.chunks.push(Chunk { bounds, centre, size, width, indices, dot: 0..0 });
+    }
+
+    /// Adds a dot for every chunk, after all the lines, in the colour of the
+    /// chunk's first vertex.
+    fn dots(&mut self) {
+        for idx in 0..self.chunks.len() {
+            let chunk = &self.chunks[idx];
+            let (centre, half) = (chunk.centre, chunk.width.max(1.0) / 2.0);
+            let first = &self.vertices[self.indices[chunk.indices.start as usize] as usize];
+            let color = Paint { rgb: first.rgb, base: first.base, hue_shift: first.hue_shift };
+            let corners = [Vec2::new(-half, -half), Vec2::new(half, -half), Vec2::new(half, half), Vec2::new(-half, half)]
+                .map(|corner| self.vertex(centre, corner, color));
+            let start = self.indices.len() as u32;
+            self.indices.extend([0, 1, 2, 0, 2, 3].map(|i| corners[i]));
+            self.chunks[idx].dot = start..self.indices.len() as u32;
+        }
+    }
+
+    /// Adds a strip of quads along the line with mitered joins, and round
+    /// caps so that lines meeting end to end show no gaps.
+    fn polyline(&mut self, points: &[DVec2], colors: &[Paint], width: f32) {
+        // drop repeated points, which have no direction
+        let mut line: Vec<(DVec2, Paint)> = Vec::with_capacity(points.len());
+        for (&p, &color) in points.iter().zip(colors.iter()) {
+            if line.last().is_none_or(|&(last, _)| last != p) {
+                line.push((p, color));
+            }
+        }
+        if line.len() < 2 {
+            return;
+        }
+
+        let half = width / 2.0;
+        self.max_width = self.max_width.max(width);
+        let dirs: Vec<Vec2> = line.windows(2).map(|w| (w[1].0 - w[0].0).normalize().as_f32()).collect();
+        let (first, last) = (line[0], line[line.len() - 1]);
+        let mut start = (self.vertices.len(), self.indices.len());
+        let mut bounds = Bounds::empty();
+        self.cap(first.0, -dirs[0], half, first.1);
+
+        let mut previous = None;
+        for (i, &(p, color)) in line.iter().enumerate() {
+            let (before, after) = (dirs[i.saturating_sub(1)], dirs[i.min(dirs.len() - 1)]);
+            let normal = before.perp();
+            let miter = (before + after).try_normalize().map_or(normal, |tangent| tangent.perp());
+            let length = (half / miter.dot(normal).abs().max(f32::EPSILON)).min(half * MITER_LIMIT);
+            let left = self.vertex(p, miter * length, color);
+            let right = self.vertex(p, -miter * length, color);
+            if let Some((prev_left, prev_right)) = previous {
+                self.indices.extend([prev_left, prev_right, right, prev_left, right, left]);
+            }
+            previous = Some((left, right));
+            bounds = bounds.with(p.as_f32());
+            if i > 0 && i % CHUNK_SEGMENTS == 0 && i < line.len() - 1 {
+                self.split(&mut start, &mut bounds, width);
+            }
+        }
+
+        self.cap(last.0, dirs[dirs.len() - 1], half, last.1);
+        self.close(start, bounds, width);
+    }
+
+    /// A half disc at the end of a line, bulging towards `dir`.
+    fn cap(&mut self, centre: DVec2, dir: Vec2, radius: f32, color: Paint) {
+        let middle = self.vertex(centre, Vec2::ZERO, color);
+        let first = self.vertices.len() as u32;
+        for step in 0..=CAP_STEPS {
+            let (sin, cos) = (step as f32 / CAP_STEPS as f32 * PI - PI / 2.0).sin_cos();
+            self.vertex(centre, (dir * cos + dir.perp() * sin) * radius, color);
+        }
+        for step in 0..CAP_STEPS {
+            self.indices.extend([middle, first + step, first + step + 1]);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use nannou::glam::{DVec2, DVec3, Vec2};
+
+/// Orbits around the origin, looking down the z axis when `yaw` and `pitch`
+/// are zero so that figures in the xy plane appear exactly as drawn.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Camera {
+    /// Rotation about the vertical axis, in radians.
+    pub yaw: f32,
+    /// Rotation about the horizontal axis, in radians.
+    pub pitch: f32,
+    /// Distance from the camera to the xy plane. Smaller values give a
+    /// stronger perspective.
+    pub distance: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            yaw: 0.0,
+            pitch: 0.0,
+            distance: 2000.0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn orbit(&mut self, delta: Vec2) {
+        self.yaw += delta.x * 0.01;
+        self.pitch = (self.pitch - delta.y * 0.01).clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2);
+    }
+
+    /// Projects a point onto the screen, scaling it so that points on the
+    /// plane through the origin facing the camera keep their size.
+    pub fn project(&self, p: DVec3) -> DVec2 {
+        let (sin_yaw, cos_yaw) = (self.yaw as f64).sin_cos();
+        let (sin_pitch, cos_pitch) = (self.pitch as f64).sin_cos();
+        let distance = self.distance as f64;
+
+        let x = p.x * cos_yaw + p.z * sin_yaw;
+        let z = p.z * cos_yaw - p.x * sin_yaw;
+        let y = p.y * cos_pitch - z * sin_pitch;
+        let z = z * cos_pitch + p.y * sin_pitch;
+
+        // keep points behind the camera from flipping through infinity
+        let depth = (distance - z).max(distance * 0.01);
+        DVec2::new(x, y) * (distance / depth)
+    }
+}
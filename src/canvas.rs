@@ -0,0 +1,232 @@
+use std::rc::Rc;
+
+use nannou::draw::{Draw, Renderer, RendererBuilder};
+use nannou::glam::DVec2;
+use nannou::wgpu;
+
+use crate::batch::Visible;
+use crate::lines::{LineRenderer, Lines, Pass, Transform};
+use crate::trail::BlendMode;
+
+/// A texture to draw into away from the window, and a renderer for it.
+pub fn offscreen(device: &wgpu::Device, size: [u32; 2]) -> (wgpu::Texture, Renderer) {
+    let texture = wgpu::TextureBuilder::new()
+        .size(size)
+        .usage(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)
+        .sample_count(1)
+        .format(wgpu::TextureFormat::Rgba16Float)
+        .build(device);
+    let renderer = RendererBuilder::new().build_from_texture_descriptor(device, texture.descriptor());
+    (texture, renderer)
+}
+
+/// Which version of the figure a canvas shows, and where.
+#[derive(Clone, Copy, PartialEq)]
+pub struct View {
+    /// Counts up each time the figure is rebuilt.
+    pub figure: u64,
+    pub offset: DVec2,
+    /// In radians.
+    pub rotation: f32,
+    pub scale: f64,
+    /// What the canvas is cleared to, so that drawing it over something else
+    /// leaves that unchanged where nothing is drawn.
+    pub blank: [f32; 4],
+}
+
+/// Fewer triangles than this say too little about how many fit in a frame.
+const MIN_MEASURED: usize = 10_000;
+/// Frames over budget in a row before the capacity is lowered, so that one
+/// slow frame doesn't count.
+const SLOW_FRAMES: u32 = 2;
+
+/// Learns how many triangles can be drawn in a frame without going over the
+/// frame budget, from how long frames take.
+pub struct Pacing {
+    /// Triangles that fit in a frame, as far as is known.
+    capacity: usize,
+    /// Triangles drawn in the frame being measured.
+    drawn: usize,
+    /// Frames in a row that went over budget.
+    slow: u32,
+}
+
+impl Default for Pacing {
+    fn default() -> Self {
+        Pacing { capacity: 1_000_000, drawn: 0, slow: 0 }
+    }
+}
+
+impl Pacing {
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Counts triangles drawn in the current frame.
+    pub fn drew(&mut self, triangles: usize) {
+        self.drawn += triangles;
+    }
+
+    /// Learns from the last frame, which took `dt` seconds against a budget
+    /// of `budget`, and starts measuring the next. Frames over budget lower
+    /// the capacity to what would have fit, and frames that drew close to it
+    /// and still had time to spare raise it.
+    pub fn measure(&mut self, dt: f32, budget: f32) {
+        let drawn = std::mem::take(&mut self.drawn);
+        if drawn < MIN_MEASURED {
+            self.slow = 0;
+            return;
+        }
+        if dt > budget {
+            self.slow += 1;
+            if self.slow >= SLOW_FRAMES {
+                self.capacity = self.capacity.min((drawn as f32 * budget / dt) as usize).max(MIN_MEASURED);
+            }
+        } else {
+            self.slow = 0;
+            if drawn * 5 >= self.capacity * 4 {
+                self.capacity = self.capacity.max(drawn + drawn / 4);
+            }
+        }
+    }
+}
+
+/// An offscreen texture the figure is drawn into a few chunks per frame, so
+/// that figures too big to draw in one frame appear progressively instead of
+/// stalling the window. The canvas is transparent where nothing is drawn.
+/// Drawing starts over whenever the figure changes, unless it is held, when
+/// the last finished drawing is shown until the new one is done.
+pub struct Canvas {
+    textures: [wgpu::Texture; 2],
+    /// Which texture is being drawn into, and which is shown.
+    drawing: usize,
+    shown: usize,
+    size: [u32; 2],
+    draw: Draw,
+    renderer: Renderer,
+    view: Option<View>,
+    lines: Option<Rc<Lines>>,
+    visible: Visible,
+    transform: Transform,
+    /// The base colour when drawing started, kept until it is done so that
+    /// cycling colours don't keep restarting it.
+    base: [f32; 3],
+    /// How many triangles have been drawn.
+    next: usize,
+    /// Whether the canvas is cleared before the next chunks.
+    clear: bool,
+    finished: bool,
+}
+
+impl Canvas {
+    pub fn new(device: &wgpu::Device, size: [u32; 2]) -> Canvas {
+        let (texture, renderer) = offscreen(device, size);
+        let (back, _) = offscreen(device, size);
+        Canvas {
+            textures: [texture, back],
+            drawing: 0,
+            shown: 0,
+            size,
+            draw: Draw::new(),
+            renderer,
+            view: None,
+            lines: None,
+            visible: Visible::default(),
+            transform: Transform { centre: DVec2::ZERO, rotation: 0.0, scale: 1.0 },
+            base: [1.0; 3],
+            next: 0,
+            clear: true,
+            finished: true,
+        }
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// What to show of the canvas.
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.textures[self.shown]
+    }
+
+    /// Whether drawing should start over for `view`. If `hold`, a change to
+    /// just the figure waits until the last drawing is done, so that figures
+    /// rebuilt every frame still get drawn.
+    pub fn stale(&self, view: View, hold: bool) -> bool {
+        match self.view {
+            None => true,
+            Some(old) if View { figure: view.figure, ..old } != view => true,
+            Some(old) => old.figure != view.figure && (self.finished || !hold),
+        }
+    }
+
+    /// Starts drawing the `visible` part of `lines` over in `view`, placed by
+    /// `transform`, with `base` as the base colour. If `hold` and only the
+    /// figure changed, the last drawing stays shown until this one is done.
+    pub fn restart(&mut self, view: View, lines: Rc<Lines>, visible: Visible, transform: Transform, base: [f32; 3], hold: bool) {
+        let moved = self.view.is_none_or(|old| View { figure: view.figure, ..old } != view);
+        self.drawing = if hold && !moved && self.finished { 1 - self.shown } else { self.shown };
+        self.clear = true;
+        self.view = Some(view);
+        self.lines = Some(lines);
+        self.visible = visible;
+        self.transform = transform;
+        self.base = base;
+        self.next = 0;
+        self.finished = false;
+    }
+
+    /// How much of the figure has been drawn, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        match self.visible.triangles() {
+            _ if self.finished => 1.0,
+            0 => 0.0,
+            total => self.next as f32 / total as f32,
+        }
+    }
+
+    /// Draws the next `budget` triangles, and returns how many were drawn.
+    /// When the last of them are drawn, `last` draws anything else on top,
+    /// with the view's rotation applied, given the base colour.
+    pub fn step(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &LineRenderer,
+        budget: usize,
+        last: impl FnOnce(&Draw, [f32; 3]),
+    ) -> usize {
+        let (Some(view), Some(lines)) = (self.view, &self.lines) else { return 0 };
+        if self.finished {
+            return 0;
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("canvas") });
+        let start = self.next;
+        let end = (start + budget.max(1)).min(self.visible.triangles());
+        let texture = &self.textures[self.drawing];
+        let target = texture.view().build();
+        let pass = Pass {
+            device,
+            target: &target,
+            size: self.size,
+            samples: 1,
+            blend: BlendMode::Normal,
+            clear: self.clear.then_some(view.blank),
+            base: self.base,
+        };
+        renderer.draw(&mut encoder, &pass, lines, &self.visible.slice(start, end).ranges, self.transform);
+        self.clear = false;
+        self.next = end;
+
+        if self.next >= self.visible.triangles() {
+            self.draw.reset();
+            last(&self.draw.rotate(view.rotation), self.base);
+            self.renderer.render_to_texture(device, &mut encoder, &self.draw, texture);
+            self.finished = true;
+            self.shown = self.drawing;
+        }
+        queue.submit(Some(encoder.finish()));
+        end - start
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+
+use crate::lsystem::Geometry;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ColorMode {
+    /// The base colour, or the palette colour set by `'`.
+    Palette,
+    Depth,
+    Generation,
+    Symbol,
+    PathLength,
+    BranchOrder,
+    Heading,
+}
+
+impl ColorMode {
+    pub const ALL: [ColorMode; 7] = [
+        ColorMode::Palette, ColorMode::Depth, ColorMode::Generation, ColorMode::Symbol,
+        ColorMode::PathLength, ColorMode::BranchOrder, ColorMode::Heading,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorMode::Palette => "palette",
+            ColorMode::Depth => "depth",
+            ColorMode::Generation => "generation",
+            ColorMode::Symbol => "symbol",
+            ColorMode::PathLength => "path-length",
+            ColorMode::BranchOrder => "branch-order",
+            ColorMode::Heading => "heading",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ColorMode> {
+        ColorMode::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+/// Colours spaced evenly from 0 to 1.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Gradient {
+    pub stops: Vec<[f32; 3]>,
+}
+
+impl Gradient {
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        match self.stops.len() {
+            0 => [1.0; 3],
+            1 => self.stops[0],
+            n => {
+                let x = t.clamp(0.0, 1.0) * (n - 1) as f32;
+                let i = (x.floor() as usize).min(n - 2);
+                let (a, b, f) = (self.stops[i], self.stops[i + 1], x - i as f32);
+                [0, 1, 2].map(|c| a[c] + (b[c] - a[c]) * f)
+            }
+        }
+    }
+}
+
+/// How segments are coloured. Segments given a palette colour with `'` keep
+/// it; the mode applies to those drawn in the base colour.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Coloring {
+    pub mode: ColorMode,
+    /// A gradient for every mode but `Palette`.
+    pub gradients: HashMap<ColorMode, Gradient>,
+    /// Slides the colours along the gradient over time.
+    pub animate: bool,
+}
+
+impl Default for Coloring {
+    fn default() -> Self {
+        let brown = [0.4, 0.25, 0.1];
+        let green = [0.3, 0.8, 0.2];
+        let gradient = |stops: &[[f32; 3]]| Gradient { stops: stops.to_vec() };
+        Coloring {
+            mode: ColorMode::Palette,
+            gradients: HashMap::from([
+                (ColorMode::Depth, gradient(&[brown, green])),
+                (ColorMode::Generation, gradient(&[[0.2, 0.3, 1.0], [1.0, 0.3, 0.6]])),
+                (ColorMode::Symbol, gradient(&[[1.0, 0.3, 0.3], [1.0, 1.0, 0.3], [0.3, 1.0, 0.5], [0.3, 0.6, 1.0]])),
+                (ColorMode::PathLength, gradient(&[[0.1, 0.1, 0.5], [0.2, 0.8, 0.9], [1.0, 1.0, 1.0]])),
+                (ColorMode::BranchOrder, gradient(&[green, brown])),
+                (ColorMode::Heading, gradient(&[[1.0, 0.2, 0.2], [0.2, 1.0, 0.2], [0.2, 0.2, 1.0], [1.0, 0.2, 0.2]])),
+            ]),
+            animate: false,
+        }
+    }
+}
+
+/// A colour that may be partly the base colour, which is left open until the
+/// figure is drawn so that cycling it doesn't rebuild the figure.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Paint {
+    pub rgb: [f32; 3],
+    /// How much of the base colour is added to `rgb`.
+    pub base: f32,
+    /// Turns round the colour wheel, once the base colour is added.
+    pub hue_shift: f32,
+}
+
+impl Paint {
+    pub const BASE: Paint = Paint { rgb: [0.0; 3], base: 1.0, hue_shift: 0.0 };
+
+    pub fn rgb(rgb: [f32; 3]) -> Paint {
+        Paint { rgb, base: 0.0, hue_shift: 0.0 }
+    }
+
+    pub fn lerp(self, other: Paint, t: f32) -> Paint {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        Paint {
+            rgb: [0, 1, 2].map(|c| mix(self.rgb[c], other.rgb[c])),
+            base: mix(self.base, other.base),
+            hue_shift: mix(self.hue_shift, other.hue_shift),
+        }
+    }
+
+    /// The rgb colour with `base` filled in.
+    pub fn resolve(&self, base: [f32; 3]) -> [f32; 3] {
+        let rgb = [0, 1, 2].map(|c| self.rgb[c] + base[c] * self.base);
+        if self.hue_shift == 0.0 {
+            return rgb;
+        }
+        let (h, s, v) = rgb_to_hsv(rgb);
+        hsv_to_rgb(h + self.hue_shift, s, v)
+    }
+}
+
+/// Resolved colours for a piece of geometry.
+pub struct Colors {
+    /// The colour of every point of every section.
+    pub sections: Vec<Vec<Paint>>,
+    pub polygons: Vec<Paint>,
+    pub stamps: Vec<Paint>,
+}
+
+impl Coloring {
+    pub fn colors(&self, geometry: &Geometry, palette: &[[f32; 3]], clock: f32) -> Colors {
+        let sections = &geometry.sections;
+        let segments = || sections.iter().flat_map(|section| section.segments.iter());
+        let max_depth = segments().map(|segment| segment.depth).max().unwrap_or(0);
+        let max_generation = segments().map(|segment| segment.generation).max().unwrap_or(0);
+        let normalize = |value: f32, max: f32| if max > 0.0 { value / max } else { 0.0 };
+
+        // where each segment sits on the gradient, and where its end point does
+        let values: Vec<Vec<(f32, f32)>> = match self.mode {
+            ColorMode::Palette => sections.iter().map(|section| vec![(0.0, 0.0); section.segments.len()]).collect(),
+            ColorMode::Depth | ColorMode::Generation => sections.iter()
+                .map(|section| section.segments.iter()
+                    .map(|segment| {
+                        let t = match self.mode {
+                            ColorMode::Depth => normalize(segment.depth as f32, max_depth as f32),
+                            _ => normalize(segment.generation as f32, max_generation as f32),
+                        };
+                        (t, t)
+                    })
+                    .collect())
+                .collect(),
+            ColorMode::Symbol => {
+                let mut symbols: Vec<char> = segments().map(|segment| segment.symbol).collect();
+                symbols.sort();
+                symbols.dedup();
+                let last = symbols.len().saturating_sub(1) as f32;
+                sections.iter()
+                    .map(|section| section.segments.iter()
+                        .map(|segment| {
+                            let t = normalize(symbols.binary_search(&segment.symbol).unwrap_or(0) as f32, last);
+                            (t, t)
+                        })
+                        .collect())
+                    .collect()
+            }
+            ColorMode::PathLength => {
+                let ends: Vec<Vec<f32>> = sections.iter()
+                    .map(|section| section.segments.iter().enumerate()
+                        .map(|(i, segment)| segment.distance + section.points[i].distance(section.points[i + 1]) as f32)
+                        .collect())
+                    .collect();
+                let longest = ends.iter().flatten().copied().fold(0.0, f32::max);
+                sections.iter().zip(ends.iter())
+                    .map(|(section, ends)| section.segments.iter().zip(ends.iter())
+                        .map(|(segment, &end)| (normalize(segment.distance, longest), normalize(end, longest)))
+                        .collect())
+                    .collect()
+            }
+            ColorMode::BranchOrder => {
+                let orders = geometry.strahler_orders();
+                let max_order = orders.iter().flatten().copied().max().unwrap_or(1);
+                orders.iter()
+                    .map(|orders| orders.iter()
+                        .map(|&order| {
+                            let t = normalize(order as f32 - 1.0, max_order as f32 - 1.0);
+                            (t, t)
+                        })
+                        .collect())
+                    .collect()
+            }
+            ColorMode::Heading => sections.iter()
+                .map(|section| section.points.windows(2)
+                    .map(|pair| {
+                        let dir = pair[1] - pair[0];
+                        let t = (dir.y.atan2(dir.x) as f32 / TAU).rem_euclid(1.0);
+                        (t, t)
+                    })
+                    .collect())
+                .collect(),
+        };
+
+        let color = |t: f32, palette_idx: usize| match self.gradients.get(&self.mode) {
+            Some(gradient) if palette_idx == 0 => Paint::rgb(gradient.sample(self.shift(t, clock))),
+            _ => palette_color(palette, palette_idx),
+        };
+
+        let section_colors = sections.iter().zip(values.iter())
+            .map(|(section, values)| {
+                let mut colors = Vec::with_capacity(section.points.len());
+                for (segment, &(start, end)) in section.segments.iter().zip(values.iter()) {
+                    if colors.is_empty() {
+                        colors.push(color(start, segment.color));
+                    }
+                    colors.push(color(end, segment.color));
+                }
+                if colors.is_empty() {
+                    colors.push(Paint::BASE);
+                }
+                colors
+            })
+            .collect();
+
+        // polygons and stamps take the value of the segment drawn before
+        // them, except where they have their own depth and generation
+        let mut drawn: Vec<(usize, f32)> = sections.iter().zip(values.iter())
+            .flat_map(|(section, values)| section.segments.iter().zip(values.iter()).map(|(segment, &(_, end))| (segment.index, end)))
+            .collect();
+        drawn.sort_by_key(|&(index, _)| index);
+        let value = |index: usize, depth: u32, generation: u32| match self.mode {
+            ColorMode::Depth => normalize(depth as f32, max_depth as f32),
+            ColorMode::Generation => normalize(generation as f32, max_generation as f32),
+            _ => match drawn.partition_point(|&(drawn, _)| drawn < index) {
+                0 => 0.0,
+                k => drawn[k - 1].1,
+            },
+        };
+        let polygons = geometry.polygons.iter()
+            .map(|polygon| color(value(polygon.index, polygon.depth, polygon.generation), polygon.color))
+            .collect();
+        let stamps = geometry.stamps.iter()
+            .map(|stamp| color(value(stamp.index, stamp.depth, stamp.generation), stamp.color))
+            .collect();
+
+        Colors { sections: section_colors, polygons, stamps }
+    }
+
+    /// Moves `t` back and forth along the gradient when animated.
+    fn shift(&self, t: f32, clock: f32) -> f32 {
+        if !self.animate {
+            return t;
+        }
+        let t = (t + clock * 0.1).rem_euclid(2.0);
+        if t > 1.0 { 2.0 - t } else { t }
+    }
+}
+
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> [f32; 3] {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let c = value * saturation;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = value - c;
+    [r + m, g + m, b + m]
+}
+
+pub fn rgb_to_hsv([r, g, b]: [f32; 3]) -> (f32, f32, f32) {
+    let max = r.max(g).max(b);
+    let c = max - r.min(g).min(b);
+    let h = if c == 0.0 {
+        0.0
+    } else if max == r {
+        ((g - b) / c).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / c + 2.0
+    } else {
+        (r - g) / c + 4.0
+    };
+    let saturation = if max > 0.0 { c / max } else { 0.0 };
+    (h / 6.0, saturation, max)
+}
+
+/// Looks up colour `idx`, where 0 is the base colour and higher indices cycle
+/// through the palette.
+pub fn palette_color(palette: &[[f32; 3]], idx: usize) -> Paint {
+    match idx {
+        0 => Paint::BASE,
+        _ if palette.is_empty() => Paint::BASE,
+        idx => Paint::rgb(palette[(idx - 1) % palette.len()]),
+    }
+}
@@ -0,0 +1,137 @@
+/// What the turtle does for a symbol.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    /// Steps forward, drawing a line.
+    Draw,
+    /// Steps forward with the pen up, starting a new line.
+    Move,
+    TurnLeft,
+    TurnRight,
+    PitchDown,
+    PitchUp,
+    RollLeft,
+    RollRight,
+    TurnAround,
+    Push,
+    Pop,
+    Thinner,
+    Thicker,
+    NextColor,
+    ScaleStep,
+    ScaleAngle,
+    BeginPolygon,
+    Vertex,
+    EndPolygon,
+    /// Stamps the surface named by the next symbol.
+    Surface,
+    NoOp,
+}
+
+impl Action {
+    pub const ALL: [Action; 21] = [
+        Action::Draw, Action::Move, Action::TurnLeft, Action::TurnRight, Action::PitchDown,
+        Action::PitchUp, Action::RollLeft, Action::RollRight, Action::TurnAround, Action::Push,
+        Action::Pop, Action::Thinner, Action::Thicker, Action::NextColor, Action::ScaleStep,
+        Action::ScaleAngle, Action::BeginPolygon, Action::Vertex, Action::EndPolygon, Action::Surface,
+        Action::NoOp,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Draw => "draw",
+            Action::Move => "move",
+            Action::TurnLeft => "turn-left",
+            Action::TurnRight => "turn-right",
+            Action::PitchDown => "pitch-down",
+            Action::PitchUp => "pitch-up",
+            Action::RollLeft => "roll-left",
+            Action::RollRight => "roll-right",
+            Action::TurnAround => "turn-around",
+            Action::Push => "push",
+            Action::Pop => "pop",
+            Action::Thinner => "thinner",
+            Action::Thicker => "thicker",
+            Action::NextColor => "next-color",
+            Action::ScaleStep => "scale-step",
+            Action::ScaleAngle => "scale-angle",
+            Action::BeginPolygon => "begin-polygon",
+            Action::Vertex => "vertex",
+            Action::EndPolygon => "end-polygon",
+            Action::Surface => "surface",
+            Action::NoOp => "no-op",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+/// The standard commands: uppercase letters draw, lowercase letters move, and
+/// the usual symbols turn, branch and so on.
+pub fn default_commands() -> Vec<(char, Action)> {
+    let mut table: Vec<(char, Action)> = ('A'..='Z').map(|c| (c, Action::Draw)).collect();
+    table.extend(('a'..='z').map(|c| (c, Action::Move)));
+    table.extend([
+        ('+', Action::TurnLeft),
+        ('-', Action::TurnRight),
+        ('&', Action::PitchDown),
+        ('^', Action::PitchUp),
+        ('\\', Action::RollLeft),
+        ('/', Action::RollRight),
+        ('|', Action::TurnAround),
+        ('[', Action::Push),
+        (']', Action::Pop),
+        ('!', Action::Thinner),
+        ('#', Action::Thicker),
+        ('\'', Action::NextColor),
+        ('"', Action::ScaleStep),
+        (';', Action::ScaleAngle),
+        ('{', Action::BeginPolygon),
+        ('.', Action::Vertex),
+        ('}', Action::EndPolygon),
+        ('~', Action::Surface),
+    ]);
+    table
+}
+
+/// If the symbol at `i` is followed by an argument list like `(15)`, returns
+/// the index of its closing bracket.
+pub fn argument_end(chars: &[char], i: usize) -> Option<usize> {
+    if chars.get(i + 1) != Some(&'(') {
+        return None;
+    }
+    chars[i + 2..].iter().position(|&c| c == ')').map(|end| i + 2 + end)
+}
+
+/// Parses the comma separated numbers between an argument list's brackets.
+pub fn parse_arguments(chars: &[char]) -> Option<Vec<f32>> {
+    let text: String = chars.iter().collect();
+    text.split(',').map(|arg| arg.trim().parse().ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    #[test]
+    fn finds_argument_lists() {
+        assert_eq!(argument_end(&chars("F(2.5)+"), 0), Some(5));
+        assert_eq!(argument_end(&chars("F+(2)"), 0), None);
+        assert_eq!(argument_end(&chars("F+(2)"), 1), Some(4));
+        // unclosed
+        assert_eq!(argument_end(&chars("F(2"), 0), None);
+    }
+
+    #[test]
+    fn parses_arguments() {
+        assert_eq!(parse_arguments(&chars("15")), Some(vec![15.0]));
+        assert_eq!(parse_arguments(&chars(" 1.5, -2 ")), Some(vec![1.5, -2.0]));
+        assert_eq!(parse_arguments(&chars("1,x")), None);
+        assert_eq!(parse_arguments(&chars("")), None);
+    }
+}
@@ -0,0 +1,198 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use nannou::glam::{DVec2, Vec2, Vec3};
+
+use crate::coloring::Paint;
+use crate::mesh::Mesh;
+use crate::scene::Shape;
+
+pub fn write_obj(mesh: &Mesh, path: &Path) -> Result<(), String> {
+    let err = |err: std::io::Error| format!("{}: {}", path.display(), err);
+    let mut out = BufWriter::new(File::create(path).map_err(err)?);
+
+    // vertex colours follow the position, as most tools read them
+    for (p, [r, g, b]) in mesh.positions.iter().zip(mesh.colors.iter()) {
+        writeln!(out, "v {} {} {} {} {} {}", p.x, p.y, p.z, r, g, b).map_err(err)?;
+    }
+    for n in mesh.normals.iter() {
+        writeln!(out, "vn {} {} {}", n.x, n.y, n.z).map_err(err)?;
+    }
+    for tri in mesh.indices.chunks(3) {
+        // obj indices start at 1
+        let (a, b, c) = (tri[0] + 1, tri[1] + 1, tri[2] + 1);
+        writeln!(out, "f {a}//{a} {b}//{b} {c}//{c}").map_err(err)?;
+    }
+    out.flush().map_err(err)
+}
+
+/// Writes a binary glTF file holding a single mesh with positions, normals,
+/// vertex colours and 32-bit indices.
+pub fn write_glb(mesh: &Mesh, path: &Path) -> Result<(), String> {
+    let mut bin: Vec<u8> = Vec::new();
+    for p in mesh.positions.iter().chain(mesh.normals.iter()) {
+        for c in p.to_array() {
+            bin.extend(c.to_le_bytes());
+        }
+    }
+    for c in mesh.colors.iter().flatten() {
+        bin.extend(c.to_le_bytes());
+    }
+    for i in mesh.indices.iter() {
+        bin.extend(i.to_le_bytes());
+    }
+
+    let count = mesh.positions.len();
+    let vec3_bytes = count * 12;
+    let (min, max) = mesh.positions.iter().fold(
+        (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+        |(min, max), &p| (min.min(p), max.max(p)),
+    );
+    let (min, max) = if count == 0 { (Vec3::ZERO, Vec3::ZERO) } else { (min, max) };
+
+    let json = format!(
+        concat!(
+            r#"{{"asset":{{"version":"2.0","generator":"lsystems"}},"#,
+            r#""scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"#,
+            r#""meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1,"COLOR_0":2}},"indices":3}}]}}],"#,
+            r#""buffers":[{{"byteLength":{}}}],"#,
+            r#""bufferViews":["#,
+            r#"{{"buffer":0,"byteOffset":0,"byteLength":{},"target":34962}},"#,
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":34962}},"#,
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":34962}},"#,
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":34963}}],"#,
+            r#""accessors":["#,
+            r#"{{"bufferView":0,"componentType":5126,"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}},"#,
+            r#"{{"bufferView":1,"componentType":5126,"count":{},"type":"VEC3"}},"#,
+            r#"{{"bufferView":2,"componentType":5126,"count":{},"type":"VEC3"}},"#,
+            r#"{{"bufferView":3,"componentType":5125,"count":{},"type":"SCALAR"}}]}}"#,
+        ),
+        bin.len(),
+        vec3_bytes,
+        vec3_bytes, vec3_bytes,
+        vec3_bytes * 2, vec3_bytes,
+        vec3_bytes * 3, mesh.indices.len() * 4,
+        count, min.x, min.y, min.z, max.x, max.y, max.z,
+        count,
+        count,
+        mesh.indices.len(),
+    );
+
+    // chunks are padded to 4 bytes, json with spaces and binary with zeros
+    let mut json = json.into_bytes();
+    json.resize(json.len().div_ceil(4) * 4, b' ');
+    bin.resize(bin.len().div_ceil(4) * 4, 0);
+
+    let total = 12 + 8 + json.len() + 8 + bin.len();
+    let mut glb = Vec::with_capacity(total);
+    glb.extend(b"glTF");
+    glb.extend(2u32.to_le_bytes());
+    glb.extend((total as u32).to_le_bytes());
+    glb.extend((json.len() as u32).to_le_bytes());
+    glb.extend(b"JSON");
+    glb.extend(json);
+    glb.extend((bin.len() as u32).to_le_bytes());
+    glb.extend(b"BIN\0");
+    glb.extend(bin);
+
+    std::fs::write(path, glb).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn svg_color([r, g, b]: [f32; 3]) -> String {
+    let byte = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", byte(r), byte(g), byte(b))
+}
+
+/// Svg strokes have a single colour, so lines and curves coloured at each
+/// point are split into runs of segments whose ends share one. Returns the
+/// first and one past the last segment of each run, with its colour.
+fn color_runs(colors: &[Paint], base: [f32; 3]) -> Vec<(usize, usize, String)> {
+    let segment_colors: Vec<String> = colors.iter().skip(1).map(|color| svg_color(color.resolve(base))).collect();
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for end in 1..=segment_colors.len() {
+        if end == segment_colors.len() || segment_colors[end] != segment_colors[start] {
+            runs.push((start, end, segment_colors[start].clone()));
+            start = end;
+        }
+    }
+    runs
+}
+
+/// Writes the shapes as an SVG on a `background` coloured background, zoomed
+/// by `scale`, rotated by `rotation` radians and fitted to their bounds, with
+/// `base` as the base colour.
+pub fn write_svg(shapes: &[Shape], base: [f32; 3], background: [f32; 3], scale: f32, rotation: f32, path: &Path) -> Result<(), String> {
+    let (sin, cos) = rotation.sin_cos();
+    // svg's y axis points down
+    let transform = |p: &DVec2| {
+        let p = p.as_f32();
+        Vec2::new(p.x * cos - p.y * sin, -(p.x * sin + p.y * cos)) * scale
+    };
+    let shapes: Vec<(Vec<Vec2>, &Shape)> = shapes.iter()
+        .map(|shape| match shape {
+            Shape::Line { points, .. } | Shape::Fill { points, .. } => (points.iter().map(transform).collect(), shape),
+            Shape::Curve { pieces, .. } => (pieces.iter().flatten().map(transform).collect(), shape),
+            Shape::Sprite { position, heading, .. } => (vec![transform(position), transform(&(*position + heading.as_f64()))], shape),
+        })
+        .collect();
+
+    let (mut min, mut max) = shapes.iter().flat_map(|(points, _)| points.iter()).fold(
+        (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+        |(min, max), &p| (min.min(p), max.max(p)),
+    );
+    if min.x > max.x {
+        (min, max) = (Vec2::ZERO, Vec2::ZERO);
+    }
+    let (min, size) = (min - Vec2::splat(10.0), max - min + Vec2::splat(20.0));
+
+    let err = |err: std::io::Error| format!("{}: {}", path.display(), err);
+    let mut out = BufWriter::new(File::create(path).map_err(err)?);
+    writeln!(out, r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#, min.x, min.y, size.x, size.y).map_err(err)?;
+    writeln!(out, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#, min.x, min.y, size.x, size.y, svg_color(background)).map_err(err)?;
+    for (points, shape) in shapes.iter() {
+        let list = |points: &[Vec2]| points.iter().map(|p| format!("{},{}", p.x, p.y)).collect::<Vec<_>>().join(" ");
+        match shape {
+            Shape::Line { width, colors, .. } => {
+                for (start, end, color) in color_runs(colors, base) {
+                    writeln!(
+                        out,
+                        r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+                        list(&points[start..=end]), color, width,
+                    ).map_err(err)?;
+                }
+                Ok(())
+            }
+            Shape::Curve { width, colors, .. } => {
+                // the control points come four to a piece
+                let pieces: Vec<&[Vec2]> = points.chunks(4).collect();
+                for (start, end, color) in color_runs(colors, base) {
+                    let first = pieces[start][0];
+                    let curves: Vec<String> = pieces[start..end].iter()
+                        .map(|piece| format!("C {},{} {},{} {},{}", piece[1].x, piece[1].y, piece[2].x, piece[2].y, piece[3].x, piece[3].y))
+                        .collect();
+                    writeln!(
+                        out,
+                        r#"<path d="M {},{} {}" fill="none" stroke="{}" stroke-width="{}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+                        first.x, first.y, curves.join(" "), color, width,
+                    ).map_err(err)?;
+                }
+                Ok(())
+            }
+            Shape::Fill { color, .. } => writeln!(out, r#"<polygon points="{}" fill="{}"/>"#, list(points), svg_color(color.resolve(base))),
+            Shape::Sprite { path: image, .. } => {
+                // the image keeps its aspect ratio inside a square box
+                let (position, heading) = (points[0], points[1] - points[0]);
+                let size = heading.length();
+                writeln!(
+                    out,
+                    r#"<image href="{}" x="{}" y="{}" width="{}" height="{}" transform="translate({} {}) rotate({})"/>"#,
+                    image, -size / 2.0, -size / 2.0, size, size, position.x, position.y, heading.y.atan2(heading.x).to_degrees(),
+                )
+            }
+        }.map_err(err)?;
+    }
+    writeln!(out, "</svg>").map_err(err)?;
+    out.flush().map_err(err)
+}
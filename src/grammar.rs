@@ -0,0 +1,545 @@
+use std::fs;
+use std::path::Path;
+
+use nannou::glam::Vec3;
+
+use crate::coloring::{ColorMode, Coloring, Gradient};
+use crate::commands::{default_commands, Action};
+use crate::jitter::{Jitter, Spread};
+use crate::modulation::{Modulation, Route, Source, Waveform};
+use crate::smoothing::{SmoothMode, Smoothing};
+use crate::surface::{Surface, SurfaceKind};
+use crate::normalize::Normalize;
+use crate::symmetry::Symmetry;
+use crate::taper::{Taper, TaperMode};
+use crate::trail::{BlendMode, Trail};
+use crate::timeline::{Easing, Keyframe, Param, Timeline};
+
+#[derive(Clone, PartialEq)]
+pub struct LSystemInput {
+    pub variables: Vec<String>,
+    pub rules: Vec<(String, String, u64)>,
+    pub start: String,
+    /// Colours selected by `'`, as rgb in 0 to 1. Colour index 0 is the base
+    /// colour set in the view.
+    pub palette: Vec<[f32; 3]>,
+    /// Shapes placed by `~` followed by their symbol.
+    pub surfaces: Vec<Surface>,
+    pub jitter: Jitter,
+    /// Jitter for particular symbols, replacing `jitter`.
+    pub symbol_jitter: Vec<(char, Jitter)>,
+    /// The turtle's starting position, heading in degrees and step multiple.
+    pub origin: Vec3,
+    pub heading: f32,
+    pub step: f32,
+    /// What `"` multiplies the step by, and `;` the angle.
+    pub length_factor: f32,
+    pub angle_factor: f32,
+    /// What each symbol makes the turtle do.
+    pub commands: Vec<(char, Action)>,
+}
+
+impl Default for LSystemInput {
+    fn default() -> Self {
+        LSystemInput {
+            variables: Vec::new(),
+            rules: Vec::new(),
+            start: String::new(),
+            palette: Vec::new(),
+            surfaces: Vec::new(),
+            jitter: Jitter::default(),
+            symbol_jitter: Vec::new(),
+            origin: Vec3::ZERO,
+            heading: 0.0,
+            step: 1.0,
+            length_factor: 0.9,
+            angle_factor: 0.9,
+            commands: default_commands(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Preset {
+    pub lsystem: LSystemInput,
+    pub level: u32,
+    pub angle: f32,
+    pub length: f32,
+    /// How the step shrinks with each generation.
+    pub normalize: Normalize,
+    /// Direction branches bend towards, and how strongly.
+    pub tropism: Vec3,
+    pub susceptibility: f32,
+    pub coloring: Coloring,
+    pub taper: Taper,
+    pub smoothing: Smoothing,
+    pub symmetry: Symmetry,
+    pub trail: Trail,
+    pub timeline: Timeline,
+    pub modulation: Modulation,
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Preset {
+            lsystem: LSystemInput::default(),
+            level: 0,
+            angle: 0.0,
+            length: 0.0,
+            normalize: Normalize::None,
+            tropism: Vec3::new(0.0, -1.0, 0.0),
+            susceptibility: 0.0,
+            coloring: Coloring::default(),
+            taper: Taper::default(),
+            smoothing: Smoothing::default(),
+            symmetry: Symmetry::default(),
+            trail: Trail::default(),
+            timeline: Timeline::default(),
+            // a slow sweep of the angle between 60° and 100°, off until enabled
+            modulation: Modulation {
+                sources: vec![Source::Lfo { waveform: Waveform::Sine, rate: 0.05, phase: 0.0 }],
+                routes: vec![Route { source: 0, target: Param::Angle, depth: 20.0, offset: 80.0, enabled: false }],
+            },
+        }
+    }
+}
+
+/// Grammar files are plain text, one `keyword arguments...` entry per line:
+///
+/// ```text
+/// start F
+/// variable F
+/// rule F 1 F[+F]F[-F]F
+/// color 0.2 0.8 0.3
+/// surface L 1.5 path M0 0 C1 -1 3 -1 4 0 C3 1 1 1 0 0
+/// surface o 0.5 polygon 6
+/// surface p 2 sprite petal.png
+/// jitter gaussian 5 0.1
+/// jitter uniform 15 0 +
+/// origin 0 -300 0
+/// heading 90
+/// step 1
+/// factors 0.7 0.9
+/// command X draw
+/// command f none
+/// level 6
+/// angle 25
+/// length 10
+/// normalize rules
+/// tropism 0 -1 0 0.2
+/// coloring depth animate
+/// gradient depth 0.4 0.25 0.1 0.3 0.8 0.2
+/// taper order 8 0.7 1
+/// smooth round 5
+/// symmetry 6 mirror 90 0.1
+/// trail 0.5 0 0 0 add
+/// timeline 10 loop
+/// key angle 0 25 ease-in-out
+/// lfo sine 0.05 0
+/// route 0 angle 20 80 on
+/// ```
+///
+/// Surfaces are `surface <symbol> <size> <kind> [arguments]`, where the kind is
+/// `circle`, `polygon <sides>`, `path <svg path data>` or `sprite <image>`.
+///
+/// `factors` sets what `"` multiplies the step by and what `;` multiplies the
+/// angle by.
+///
+/// Commands are saved as changes to the default table, with `none` for a
+/// symbol that has been removed.
+///
+/// Jitter is `jitter <spread> <angle> <length> [symbol]`, applying to every turn
+/// and step or only to those of `symbol`.
+///
+/// `coloring <mode> animate|static` picks how segments are coloured, and
+/// `gradient <mode> <r g b>...` sets a mode's gradient stops.
+///
+/// `taper <none|depth|order> <base> <ratio> <min>` sets how stroke width falls
+/// off towards the tips.
+///
+/// `smooth <none|catmull-rom|bezier|round> <radius>` sets how lines are
+/// smoothed into curves, with the radius used by `round`.
+///
+/// `normalize <none|rules|bounds>` shrinks the step with each generation so
+/// the figure keeps its size.
+///
+/// `symmetry <copies> mirror|none <axis> <hue shift>` repeats the figure
+/// around the origin, optionally with its mirror image across the axis, and
+/// turns each copy's hue by the shift.
+///
+/// `trail <fade> <r g b> <normal|add|lighten|darken>` sets how much of earlier
+/// frames fades each second, the background they fade into and how the
+/// figure is blended over them.
+///
+/// Sources (`lfo`, `noise`, `envelope`) are numbered in the order they appear.
+///
+/// Blank lines and lines starting with `#` are ignored.
+impl Preset {
+    pub fn load(path: &Path) -> Result<Preset, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Preset::from_text(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![format!("start {}", self.lsystem.start)];
+        for v in self.lsystem.variables.iter() {
+            lines.push(format!("variable {}", v));
+        }
+        for (key, val, weight) in self.lsystem.rules.iter() {
+            lines.push(format!("rule {} {} {}", key, weight, val));
+        }
+        for [r, g, b] in self.lsystem.palette.iter() {
+            lines.push(format!("color {} {} {}", r, g, b));
+        }
+        for surface in self.lsystem.surfaces.iter() {
+            let kind = match &surface.kind {
+                SurfaceKind::Circle => "circle".to_string(),
+                SurfaceKind::Polygon(sides) => format!("polygon {}", sides),
+                SurfaceKind::Path(data) => format!("path {}", data),
+                SurfaceKind::Sprite(image) => format!("sprite {}", image),
+            };
+            lines.push(format!("surface {} {} {}", surface.symbol, surface.size, kind));
+        }
+        let jitter = self.lsystem.jitter;
+        lines.push(format!("jitter {} {} {}", jitter.spread.name(), jitter.angle, jitter.length));
+        for (symbol, jitter) in self.lsystem.symbol_jitter.iter() {
+            lines.push(format!("jitter {} {} {} {}", jitter.spread.name(), jitter.angle, jitter.length, symbol));
+        }
+        let origin = self.lsystem.origin;
+        lines.push(format!("origin {} {} {}", origin.x, origin.y, origin.z));
+        lines.push(format!("heading {}", self.lsystem.heading));
+        lines.push(format!("step {}", self.lsystem.step));
+        lines.push(format!("factors {} {}", self.lsystem.length_factor, self.lsystem.angle_factor));
+        let commands = &self.lsystem.commands;
+        let defaults = default_commands();
+        for (symbol, _) in defaults.iter() {
+            if !commands.iter().any(|(c, _)| c == symbol) {
+                lines.push(format!("command {} none", symbol));
+            }
+        }
+        for command in commands.iter() {
+            if !defaults.contains(command) {
+                lines.push(format!("command {} {}", command.0, command.1.name()));
+            }
+        }
+        lines.push(format!("level {}", self.level));
+        lines.push(format!("angle {}", self.angle));
+        lines.push(format!("length {}", self.length));
+        lines.push(format!("normalize {}", self.normalize.name()));
+        let t = self.tropism;
+        lines.push(format!("tropism {} {} {} {}", t.x, t.y, t.z, self.susceptibility));
+        let coloring = &self.coloring;
+        lines.push(format!("coloring {} {}", coloring.mode.name(), if coloring.animate { "animate" } else { "static" }));
+        for mode in ColorMode::ALL.iter() {
+            if let Some(gradient) = coloring.gradients.get(mode) {
+                let stops: Vec<String> = gradient.stops.iter().flatten().map(|c| c.to_string()).collect();
+                lines.push(format!("gradient {} {}", mode.name(), stops.join(" ")));
+            }
+        }
+        let taper = self.taper;
+        lines.push(format!("taper {} {} {} {}", taper.mode.name(), taper.base, taper.ratio, taper.min));
+        lines.push(format!("smooth {} {}", self.smoothing.mode.name(), self.smoothing.radius));
+        let symmetry = self.symmetry;
+        lines.push(format!(
+            "symmetry {} {} {} {}",
+            symmetry.copies, if symmetry.mirror { "mirror" } else { "none" }, symmetry.axis, symmetry.hue_shift,
+        ));
+        let trail = self.trail;
+        let [r, g, b] = trail.background;
+        lines.push(format!("trail {} {} {} {} {}", trail.fade, r, g, b, trail.blend.name()));
+
+        let timeline = &self.timeline;
+        lines.push(format!("timeline {} {}", timeline.duration, if timeline.looping { "loop" } else { "once" }));
+        for track in timeline.tracks.iter() {
+            for key in track.keys() {
+                lines.push(format!("key {} {} {} {}", track.param.name(), key.time, key.value, key.easing.name()));
+            }
+        }
+
+        for source in self.modulation.sources.iter() {
+            lines.push(match *source {
+                Source::Lfo { waveform, rate, phase } => format!("lfo {} {} {}", waveform.name(), rate, phase),
+                Source::Noise { rate, seed } => format!("noise {} {}", rate, seed),
+                Source::Envelope { attack, hold, release, looping, .. } => {
+                    format!("envelope {} {} {} {}", attack, hold, release, if looping { "loop" } else { "once" })
+                }
+            });
+        }
+        for route in self.modulation.routes.iter() {
+            lines.push(format!("route {} {} {} {} {}", route.source, route.target.name(), route.depth, route.offset, if route.enabled { "on" } else { "off" }));
+        }
+
+        lines.join("\n") + "\n"
+    }
+
+    pub fn from_text(text: &str) -> Result<Preset, String> {
+        let mut preset = Preset {
+            modulation: Modulation::default(),
+            ..Preset::default()
+        };
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: &str| format!("line {}: {}", idx + 1, msg);
+            let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let args: Vec<&str> = rest.split_whitespace().collect();
+
+            match keyword {
+                "start" => preset.lsystem.start = rest.trim().to_string(),
+                "variable" => preset.lsystem.variables.push(rest.trim().to_string()),
+                "rule" => {
+                    let mut parts = rest.trim().splitn(3, ' ');
+                    let (Some(key), Some(weight)) = (parts.next(), parts.next()) else {
+                        return Err(err("expected `rule <variable> <weight> <replacement>`"));
+                    };
+                    let weight = weight.parse().map_err(|_| err("invalid rule weight"))?;
+                    let val = parts.next().unwrap_or("").to_string();
+                    preset.lsystem.rules.push((key.to_string(), val, weight));
+                }
+                "color" => preset.lsystem.palette.push([
+                    parse_arg(&args, 0).map_err(|e| err(&e))?,
+                    parse_arg(&args, 1).map_err(|e| err(&e))?,
+                    parse_arg(&args, 2).map_err(|e| err(&e))?,
+                ]),
+                "surface" => {
+                    let mut parts = rest.trim().splitn(4, ' ');
+                    let (Some(symbol), Some(size), Some(kind)) = (parts.next(), parts.next(), parts.next()) else {
+                        return Err(err("expected `surface <symbol> <size> <kind> [arguments]`"));
+                    };
+                    let mut chars = symbol.chars();
+                    let (Some(symbol), None) = (chars.next(), chars.next()) else {
+                        return Err(err("surface symbols are a single character"));
+                    };
+                    let size = size.parse().map_err(|_| err("invalid surface size"))?;
+                    let arg = parts.next().unwrap_or("").trim().to_string();
+                    let kind = match kind {
+                        "circle" => SurfaceKind::Circle,
+                        "polygon" => SurfaceKind::Polygon(arg.parse().map_err(|_| err("invalid polygon sides"))?),
+                        "path" => SurfaceKind::Path(arg),
+                        "sprite" => SurfaceKind::Sprite(arg),
+                        _ => return Err(err("unknown surface kind")),
+                    };
+                    preset.lsystem.surfaces.push(Surface { symbol, kind, size });
+                }
+                "jitter" => {
+                    let spread = args.first().and_then(|name| Spread::from_name(name))
+                        .ok_or_else(|| err("unknown spread"))?;
+                    let jitter = Jitter {
+                        spread,
+                        angle: parse_arg(&args, 1).map_err(|e| err(&e))?,
+                        length: parse_arg(&args, 2).map_err(|e| err(&e))?,
+                    };
+                    match args.get(3) {
+                        Some(symbol) => {
+                            let symbol = symbol.chars().next().unwrap();
+                            preset.lsystem.symbol_jitter.push((symbol, jitter));
+                        }
+                        None => preset.lsystem.jitter = jitter,
+                    }
+                }
+                "origin" => preset.lsystem.origin = Vec3::new(
+                    parse_arg(&args, 0).map_err(|e| err(&e))?,
+                    parse_arg(&args, 1).map_err(|e| err(&e))?,
+                    parse_arg(&args, 2).map_err(|e| err(&e))?,
+                ),
+                "heading" => preset.lsystem.heading = parse_arg(&args, 0).map_err(|e| err(&e))?,
+                "step" => preset.lsystem.step = parse_arg(&args, 0).map_err(|e| err(&e))?,
+                "factors" => {
+                    preset.lsystem.length_factor = parse_arg(&args, 0).map_err(|e| err(&e))?;
+                    preset.lsystem.angle_factor = parse_arg(&args, 1).map_err(|e| err(&e))?;
+                }
+                "command" => {
+                    let (Some(symbol), Some(name)) = (args.first().and_then(|s| s.chars().next()), args.get(1)) else {
+                        return Err(err("expected `command <symbol> <action>`"));
+                    };
+                    let commands = &mut preset.lsystem.commands;
+                    commands.retain(|(c, _)| *c != symbol);
+                    if *name != "none" {
+                        let action = Action::from_name(name).ok_or_else(|| err("unknown action"))?;
+                        commands.push((symbol, action));
+                    }
+                }
+                "level" => preset.level = parse_arg(&args, 0).map_err(|e| err(&e))?,
+                "angle" => preset.angle = parse_arg(&args, 0).map_err(|e| err(&e))?,
+                "length" => preset.length = parse_arg(&args, 0).map_err(|e| err(&e))?,
+                "normalize" => preset.normalize = args.first().and_then(|name| Normalize::from_name(name))
+                    .ok_or_else(|| err("unknown normalization"))?,
+                "tropism" => {
+                    preset.tropism = Vec3::new(
+                        parse_arg(&args, 0).map_err(|e| err(&e))?,
+                        parse_arg(&args, 1).map_err(|e| err(&e))?,
+                        parse_arg(&args, 2).map_err(|e| err(&e))?,
+                    );
+                    preset.susceptibility = parse_arg(&args, 3).map_err(|e| err(&e))?;
+                }
+                "coloring" => {
+                    preset.coloring.mode = args.first().and_then(|name| ColorMode::from_name(name))
+                        .ok_or_else(|| err("unknown colouring mode"))?;
+                    preset.coloring.animate = args.get(1) == Some(&"animate");
+                }
+                "gradient" => {
+                    let mode = args.first().and_then(|name| ColorMode::from_name(name))
+                        .ok_or_else(|| err("unknown colouring mode"))?;
+                    let values = (1..args.len()).map(|idx| parse_arg(&args, idx)).collect::<Result<Vec<f32>, _>>()
+                        .map_err(|e| err(&e))?;
+                    if values.len() % 3 != 0 {
+                        return Err(err("gradient stops are three numbers each"));
+                    }
+                    let stops = values.chunks(3).map(|c| [c[0], c[1], c[2]]).collect();
+                    preset.coloring.gradients.insert(mode, Gradient { stops });
+                }
+                "taper" => preset.taper = Taper {
+                    mode: args.first().and_then(|name| TaperMode::from_name(name))
+                        .ok_or_else(|| err("unknown taper mode"))?,
+                    base: parse_arg(&args, 1).map_err(|e| err(&e))?,
+                    ratio: parse_arg(&args, 2).map_err(|e| err(&e))?,
+                    min: parse_arg(&args, 3).map_err(|e| err(&e))?,
+                },
+                "smooth" => preset.smoothing = Smoothing {
+                    mode: args.first().and_then(|name| SmoothMode::from_name(name))
+                        .ok_or_else(|| err("unknown smoothing"))?,
+                    radius: parse_arg(&args, 1).map_err(|e| err(&e))?,
+                },
+                "symmetry" => preset.symmetry = Symmetry {
+                    copies: parse_arg::<u32>(&args, 0).map_err(|e| err(&e))?.max(1),
+                    mirror: args.get(1) == Some(&"mirror"),
+                    axis: parse_arg(&args, 2).map_err(|e| err(&e))?,
+                    hue_shift: parse_arg(&args, 3).map_err(|e| err(&e))?,
+                },
+                "trail" => preset.trail = Trail {
+                    fade: parse_arg(&args, 0).map_err(|e| err(&e))?,
+                    background: [
+                        parse_arg(&args, 1).map_err(|e| err(&e))?,
+                        parse_arg(&args, 2).map_err(|e| err(&e))?,
+                        parse_arg(&args, 3).map_err(|e| err(&e))?,
+                    ],
+                    blend: args.get(4).and_then(|name| BlendMode::from_name(name))
+                        .ok_or_else(|| err("unknown blend mode"))?,
+                },
+                "timeline" => {
+                    preset.timeline.duration = parse_arg(&args, 0).map_err(|e| err(&e))?;
+                    preset.timeline.looping = args.get(1) != Some(&"once");
+                }
+                "key" => {
+                    let param = args.first().and_then(|name| Param::from_name(name))
+                        .ok_or_else(|| err("unknown parameter"))?;
+                    let easing = match args.get(3) {
+                        Some(name) => Easing::from_name(name).ok_or_else(|| err("unknown easing"))?,
+                        None => Easing::Linear,
+                    };
+                    preset.timeline.track_mut(param).insert(Keyframe {
+                        time: parse_arg(&args, 1).map_err(|e| err(&e))?,
+                        value: parse_arg(&args, 2).map_err(|e| err(&e))?,
+                        easing,
+                    });
+                }
+                "lfo" => {
+                    let waveform = args.first().and_then(|name| Waveform::from_name(name))
+                        .ok_or_else(|| err("unknown waveform"))?;
+                    preset.modulation.sources.push(Source::Lfo {
+                        waveform,
+                        rate: parse_arg(&args, 1).map_err(|e| err(&e))?,
+                        phase: parse_arg(&args, 2).map_err(|e| err(&e))?,
+                    });
+                }
+                "noise" => preset.modulation.sources.push(Source::Noise {
+                    rate: parse_arg(&args, 0).map_err(|e| err(&e))?,
+                    seed: parse_arg(&args, 1).map_err(|e| err(&e))?,
+                }),
+                "envelope" => preset.modulation.sources.push(Source::Envelope {
+                    attack: parse_arg(&args, 0).map_err(|e| err(&e))?,
+                    hold: parse_arg(&args, 1).map_err(|e| err(&e))?,
+                    release: parse_arg(&args, 2).map_err(|e| err(&e))?,
+                    looping: args.get(3) == Some(&"loop"),
+                    start: 0.0,
+                }),
+                "route" => {
+                    let target = args.get(1).and_then(|name| Param::from_name(name))
+                        .ok_or_else(|| err("unknown parameter"))?;
+                    preset.modulation.routes.push(Route {
+                        source: parse_arg(&args, 0).map_err(|e| err(&e))?,
+                        target,
+                        depth: parse_arg(&args, 2).map_err(|e| err(&e))?,
+                        offset: parse_arg(&args, 3).map_err(|e| err(&e))?,
+                        enabled: args.get(4) != Some(&"off"),
+                    });
+                }
+                _ => return Err(err(&format!("unknown keyword `{}`", keyword))),
+            }
+        }
+
+        Ok(preset)
+    }
+}
+
+fn parse_arg<T: std::str::FromStr>(args: &[&str], idx: usize) -> Result<T, String> {
+    let arg = args.get(idx).ok_or("missing argument")?;
+    arg.parse().map_err(|_| format!("invalid argument `{}`", arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\
+start X
+variable X
+rule X 2 F[+X]F[-X]+X
+rule X 1 F[-X]X
+color 0.2 0.8 0.3
+surface L 1.5 path M0 0 C1 -1 3 -1 4 0 C3 1 1 1 0 0
+surface p 2 sprite petal.png
+jitter gaussian 5 0.1
+jitter uniform 15 0 +
+origin 0 -300 0
+heading 90
+factors 0.7 0.9
+command A move
+command f none
+level 6
+angle 25
+normalize rules
+tropism 0 -1 0 0.2
+gradient depth 0.4 0.25 0.1 0.3 0.8 0.2
+taper order 8 0.7 1
+symmetry 6 mirror 90 0.1
+trail 0.5 0.1 0 0 add
+timeline 12 once
+key angle 0 25 ease-in-out
+key step 4 1.5 step
+lfo triangle 0.05 0.25
+envelope 1 2 3 loop
+route 1 trail-fade 0.5 0.2 on
+";
+
+    #[test]
+    fn text_round_trips() {
+        let preset = Preset::from_text(EXAMPLE).unwrap();
+        let text = preset.to_text();
+        let reloaded = Preset::from_text(&text).unwrap();
+        assert_eq!(reloaded.to_text(), text);
+        assert!(reloaded.lsystem == preset.lsystem);
+        assert_eq!(reloaded.timeline, preset.timeline);
+        assert_eq!(reloaded.modulation, preset.modulation);
+        assert_eq!(reloaded.trail, preset.trail);
+        assert_eq!(reloaded.taper, preset.taper);
+        assert_eq!(reloaded.lsystem.rules.len(), 2);
+        assert!(reloaded.lsystem.commands.contains(&('A', Action::Move)));
+        assert!(!reloaded.lsystem.commands.iter().any(|(symbol, _)| *symbol == 'f'));
+        assert_eq!(reloaded.timeline.tracks[1].param, Param::Step);
+        assert_eq!(reloaded.modulation.routes[0].target, Param::TrailFade);
+    }
+
+    #[test]
+    fn reports_the_bad_line() {
+        let err = Preset::from_text("start F\nlevel many\n").err().unwrap();
+        assert!(err.starts_with("line 2"), "{}", err);
+    }
+}
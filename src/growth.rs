@@ -0,0 +1,87 @@
+use crate::coloring::Paint;
+use crate::lsystem::{Geometry, Stroke};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GrowthOrder {
+    String,
+    Depth,
+    Generation,
+}
+
+impl GrowthOrder {
+    pub const ALL: [GrowthOrder; 3] = [GrowthOrder::String, GrowthOrder::Depth, GrowthOrder::Generation];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GrowthOrder::String => "string order",
+            GrowthOrder::Depth => "bracket depth",
+            GrowthOrder::Generation => "generation",
+        }
+    }
+
+    fn key(&self, index: usize, depth: u32, generation: u32) -> (u32, usize) {
+        match self {
+            GrowthOrder::String => (0, index),
+            GrowthOrder::Depth => (depth, index),
+            GrowthOrder::Generation => (generation, index),
+        }
+    }
+}
+
+/// Returns the strokes visible at `progress` (0 to 1) through the growth
+/// animation, along with the indices of the visible polygons and stamps.
+/// Segments are revealed one at a time in `order`, with the segment currently
+/// growing drawn partially. Polygons and stamps appear once every segment
+/// ordered before them has. `colors` holds the colour of each section's
+/// points and `widths` the width of its segments.
+pub fn reveal(
+    geometry: &Geometry,
+    colors: &[Vec<Paint>],
+    widths: &[Vec<f32>],
+    order: GrowthOrder,
+    progress: f32,
+) -> (Vec<Stroke>, Vec<usize>, Vec<usize>) {
+    let sections = &geometry.sections;
+    let mut ranked: Vec<(usize, usize)> = sections.iter().enumerate()
+        .flat_map(|(s, section)| (0..section.segments.len()).map(move |i| (s, i)))
+        .collect();
+    let segment_key = |&(s, i): &(usize, usize)| {
+        let segment = &sections[s].segments[i];
+        order.key(segment.index, segment.depth, segment.generation)
+    };
+    ranked.sort_by_key(segment_key);
+
+    let mut ranks: Vec<Vec<usize>> = sections.iter().map(|section| vec![0; section.segments.len()]).collect();
+    for (rank, (s, i)) in ranked.iter().enumerate() {
+        ranks[*s][*i] = rank;
+    }
+
+    let shown = progress.clamp(0.0, 1.0) * ranked.len() as f32;
+    let complete = shown.floor() as usize;
+    let partial = shown.fract();
+
+    let strokes = sections.iter().zip(ranks.iter()).zip(colors.iter().zip(widths.iter()))
+        .flat_map(|((section, section_ranks), (colors, widths))| section.partial_strokes(colors, widths, |i| {
+            match section_ranks[i] {
+                rank if rank < complete => 1.0,
+                rank if rank == complete => partial,
+                _ => 0.0,
+            }
+        }))
+        .collect();
+
+    let visible = |index: usize, depth: u32, generation: u32| {
+        let key = order.key(index, depth, generation);
+        shown > 0.0 && ranked.partition_point(|segment| segment_key(segment) < key) <= complete
+    };
+    let polygons = geometry.polygons.iter().enumerate()
+        .filter(|(_, polygon)| visible(polygon.index, polygon.depth, polygon.generation))
+        .map(|(idx, _)| idx)
+        .collect();
+    let stamps = geometry.stamps.iter().enumerate()
+        .filter(|(_, stamp)| visible(stamp.index, stamp.depth, stamp.generation))
+        .map(|(idx, _)| idx)
+        .collect();
+
+    (strokes, polygons, stamps)
+}
@@ -0,0 +1,61 @@
+use std::f32::consts::TAU;
+
+use nannou::rand::Rng;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Spread {
+    Uniform,
+    Gaussian,
+}
+
+impl Spread {
+    pub const ALL: [Spread; 2] = [Spread::Uniform, Spread::Gaussian];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Spread::Uniform => "uniform",
+            Spread::Gaussian => "gaussian",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Spread> {
+        Spread::ALL.into_iter().find(|spread| spread.name() == name)
+    }
+
+    /// A random offset with the given half-width for uniform spreads, or
+    /// standard deviation for gaussian ones.
+    pub fn sample(&self, rng: &mut impl Rng, amount: f32) -> f32 {
+        if amount == 0.0 {
+            return 0.0;
+        }
+        match self {
+            Spread::Uniform => rng.gen_range(-1.0..=1.0) * amount,
+            Spread::Gaussian => {
+                // Box-Muller
+                let u: f32 = 1.0 - rng.gen::<f32>();
+                let v: f32 = rng.gen();
+                (-2.0 * u.ln()).sqrt() * (TAU * v).cos() * amount
+            }
+        }
+    }
+}
+
+/// Random variation added to each turn and step.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Jitter {
+    pub spread: Spread,
+    /// In degrees.
+    pub angle: f32,
+    /// As a fraction of the step length.
+    pub length: f32,
+}
+
+impl Default for Jitter {
+    fn default() -> Self {
+        Jitter {
+            spread: Spread::Uniform,
+            angle: 0.0,
+            length: 0.0,
+        }
+    }
+}
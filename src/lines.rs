@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use nannou::glam::{DVec2, Vec2};
+use nannou::prelude::BLEND_NORMAL;
+use nannou::wgpu::{self, util::DeviceExt};
+
+use crate::batch::LineBatch;
+use crate::coloring::Paint;
+use crate::trail::BlendMode;
+
+/// A corner of a line's triangles, as the GPU reads it.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Vertex {
+    /// The point on the line, in the figure's units. Split into the nearest
+    /// single precision value and what is left over, so that the GPU can take
+    /// it relative to the view without losing the precision deep zooms need.
+    pub point: [f32; 2],
+    pub point_low: [f32; 2],
+    /// From the point, in pixels.
+    pub offset: [f32; 2],
+    pub rgb: [f32; 3],
+    pub base: f32,
+    pub hue_shift: f32,
+}
+
+impl Vertex {
+    pub fn new(point: DVec2, offset: Vec2, paint: Paint) -> Vertex {
+        let high = point.as_f32();
+        Vertex {
+            point: high.to_array(),
+            point_low: (point - high.as_f64()).as_f32().to_array(),
+            offset: offset.to_array(),
+            rgb: paint.rgb,
+            base: paint.base,
+            hue_shift: paint.hue_shift,
+        }
+    }
+
+    /// The point on the line, in double precision.
+    pub fn point(&self) -> DVec2 {
+        Vec2::from(self.point).as_f64() + Vec2::from(self.point_low).as_f64()
+    }
+}
+
+/// How the figure sits in what lines are drawn to: zoomed by `scale` about
+/// `centre`, which ends up in the middle, then turned by `rotation` radians.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Transform {
+    pub centre: DVec2,
+    pub rotation: f32,
+    pub scale: f64,
+}
+
+/// Where one pass of lines is drawn to, and how.
+pub struct Pass<'a> {
+    pub device: &'a wgpu::Device,
+    pub target: &'a wgpu::TextureViewHandle,
+    /// In pixels.
+    pub size: [u32; 2],
+    pub samples: u32,
+    pub blend: BlendMode,
+    /// What the target is cleared to first, if anything.
+    pub clear: Option<[f32; 4]>,
+    /// Fills in the base colour.
+    pub base: [f32; 3],
+}
+
+/// The uniforms of `lines.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Uniforms {
+    centre: [f32; 4],
+    transform: [f32; 4],
+    screen: [f32; 4],
+    base: [f32; 4],
+}
+
+/// A line batch's vertices and indices, uploaded once and drawn from for as
+/// long as the figure stays the same.
+pub struct Lines {
+    vertices: wgpu::Buffer,
+    indices: wgpu::Buffer,
+}
+
+impl Lines {
+    pub fn new(device: &wgpu::Device, batch: &LineBatch) -> Lines {
+        let buffer = |label, contents: &[u8], usage| device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents,
+            usage,
+        });
+        let vertices = unsafe { wgpu::bytes::from_slice(&batch.vertices) };
+        let indices = unsafe { wgpu::bytes::from_slice(&batch.indices) };
+        Lines {
+            vertices: buffer("line vertices", vertices, wgpu::BufferUsages::VERTEX),
+            indices: buffer("line indices", indices, wgpu::BufferUsages::INDEX),
+        }
+    }
+}
+
+/// Draws lines straight from their buffers, with the view passed in as a
+/// uniform, so that panning and zooming cost nothing on the CPU.
+pub struct LineRenderer {
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// One for each sample count and blend mode.
+    pipelines: HashMap<(u32, BlendMode), wgpu::RenderPipeline>,
+}
+
+impl LineRenderer {
+    /// A renderer for targets with any of `sample_counts`, all of them in
+    /// nannou's frame format.
+    pub fn new(device: &wgpu::Device, sample_counts: &[u32]) -> LineRenderer {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("lines"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("lines.wgsl").into()),
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lines"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("lines"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let attributes = wgpu::vertex_attr_array![
+            0 => Float32x2, 1 => Float32x2, 2 => Float32x2, 3 => Float32x3, 4 => Float32, 5 => Float32,
+        ];
+
+        let mut pipelines = HashMap::new();
+        for &samples in sample_counts.iter() {
+            for blend in BlendMode::ALL {
+                let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("lines"),
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[wgpu::VertexBufferLayout {
+                            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                            step_mode: wgpu::VertexStepMode::Vertex,
+                            attributes: &attributes,
+                        }],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: nannou::Frame::TEXTURE_FORMAT,
+                            blend: Some(wgpu::BlendState {
+                                color: blend.component(),
+                                alpha: BLEND_NORMAL,
+                            }),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState { count: samples, ..Default::default() },
+                    multiview: None,
+                });
+                pipelines.insert((samples, blend), pipeline);
+            }
+        }
+        LineRenderer { bind_group_layout, pipelines }
+    }
+
+    /// Encodes drawing the `ranges` of `lines`' indices in `pass`.
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pass: &Pass,
+        lines: &Lines,
+        ranges: &[Range<u32>],
+        transform: Transform,
+    ) {
+        let Some(pipeline) = self.pipelines.get(&(pass.samples, pass.blend)) else {
+            println!("no line pipeline for {} samples", pass.samples);
+            return;
+        };
+        let centre = transform.centre.as_f32();
+        let low = (transform.centre - centre.as_f64()).as_f32();
+        let (sin, cos) = transform.rotation.sin_cos();
+        let uniforms = Uniforms {
+            centre: [centre.x, centre.y, low.x, low.y],
+            transform: [cos, sin, transform.scale as f32, 0.0],
+            screen: [2.0 / pass.size[0] as f32, 2.0 / pass.size[1] as f32, 0.0, 0.0],
+            base: [pass.base[0], pass.base[1], pass.base[2], 0.0],
+        };
+        let buffer = pass.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("line uniforms"),
+            contents: unsafe { wgpu::bytes::from(&uniforms) },
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = pass.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lines"),
+            layout: &self.bind_group_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: buffer.as_entire_binding() }],
+        });
+
+        let load = match pass.clear {
+            Some([r, g, b, a]) => wgpu::LoadOp::Clear(wgpu::Color { r: r as f64, g: g as f64, b: b as f64, a: a as f64 }),
+            None => wgpu::LoadOp::Load,
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("lines"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: pass.target,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: true },
+            })],
+            depth_stencil_attachment: None,
+        });
+        if ranges.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.set_vertex_buffer(0, lines.vertices.slice(..));
+        render_pass.set_index_buffer(lines.indices.slice(..), wgpu::IndexFormat::Uint32);
+        for range in ranges.iter() {
+            render_pass.draw_indexed(range.clone(), 0, 0..1);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+struct Uniforms {
+    // the point that ends up at the middle of the target, split into a high
+    // and a low part like the points are
+    centre: vec4<f32>,
+    // cos and sin of the rotation, and the zoom
+    transform: vec4<f32>,
+    // from pixels to clip space
+    screen: vec4<f32>,
+    base: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+};
+
+fn to_hsv(c: vec3<f32>) -> vec3<f32> {
+    let high = max(max(c.r, c.g), c.b);
+    let chroma = high - min(min(c.r, c.g), c.b);
+    var h = 0.0;
+    if chroma == 0.0 {
+        h = 0.0;
+    } else if high == c.r {
+        let x = (c.g - c.b) / chroma;
+        h = x - 6.0 * floor(x / 6.0);
+    } else if high == c.g {
+        h = (c.b - c.r) / chroma + 2.0;
+    } else {
+        h = (c.r - c.g) / chroma + 4.0;
+    }
+    var s = 0.0;
+    if high > 0.0 {
+        s = chroma / high;
+    }
+    return vec3<f32>(h / 6.0, s, high);
+}
+
+fn from_hsv(hsv: vec3<f32>) -> vec3<f32> {
+    let k = abs(fract(hsv.x + vec3<f32>(1.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0 - 3.0) - 1.0;
+    return hsv.z * mix(vec3<f32>(1.0), clamp(k, vec3<f32>(0.0), vec3<f32>(1.0)), hsv.y);
+}
+
+fn to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+@vertex
+fn vs_main(
+    @location(0) point: vec2<f32>,
+    @location(1) point_low: vec2<f32>,
+    @location(2) offset: vec2<f32>,
+    @location(3) rgb: vec3<f32>,
+    @location(4) base: f32,
+    @location(5) hue_shift: f32,
+) -> VertexOutput {
+    // the high parts cancel out exactly when the point is near the centre,
+    // leaving a difference small enough for single precision
+    let relative = (point - uniforms.centre.xy) + (point_low - uniforms.centre.zw);
+    let p = relative * uniforms.transform.z + offset;
+    let c = uniforms.transform.x;
+    let s = uniforms.transform.y;
+    let turned = vec2<f32>(p.x * c - p.y * s, p.x * s + p.y * c);
+
+    var color = rgb + uniforms.base.rgb * base;
+    if hue_shift != 0.0 {
+        let hsv = to_hsv(color);
+        color = from_hsv(vec3<f32>(hsv.x + hue_shift, hsv.y, hsv.z));
+    }
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(turned * uniforms.screen.xy, 0.0, 1.0);
+    out.color = to_linear(color);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
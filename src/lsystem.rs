@@ -1,92 +1,750 @@
 use std::collections::HashMap;
 
-use nannou::glam::Vec2;
+use nannou::glam::{DVec3, Vec3};
 use nannou::rand::prelude::SliceRandom;
 use rand_chacha::{rand_core::SeedableRng, ChaCha12Rng};
 
+use crate::coloring::Paint;
+use crate::commands::{argument_end, default_commands, parse_arguments, Action};
+use crate::jitter::Jitter;
+use crate::normalize::Normalize;
 use crate::turtle::Turtle;
 
 pub struct LSystem {
     pub start: String,
     pub rules: HashMap<String, Vec<(String, u64)>>,
-    pub length: u32,
+    pub length: f32,
     pub angle: f32,
+    /// Direction branches bend towards after every drawn step.
+    pub tropism: Vec3,
+    /// How strongly branches bend towards `tropism`. Zero turns bending off.
+    pub susceptibility: f32,
+    /// Random variation of every turn and step.
+    pub jitter: Jitter,
+    /// Replaces `jitter` for particular symbols.
+    pub symbol_jitter: HashMap<char, Jitter>,
+    /// Where the turtle starts, before scaling.
+    pub origin: Vec3,
+    /// The turtle's starting heading, in degrees anticlockwise from x.
+    pub heading: f32,
+    /// The turtle's starting step, as a multiple of `length`.
+    pub step: f32,
+    /// What `"` multiplies the step by.
+    pub length_factor: f32,
+    /// What `;` multiplies the angle by.
+    pub angle_factor: f32,
+    /// How the step shrinks with each generation.
+    pub normalize: Normalize,
+    /// What each symbol makes the turtle do. Symbols without an entry do
+    /// nothing.
+    pub commands: HashMap<char, Action>,
+    seed: u64,
     rng: ChaCha12Rng,
 }
 
-pub(crate) const VALID_CHARS: [char; 4] = ['+', '-', '[', ']'];
+/// A single drawn segment, tagged with enough information to order it in the
+/// growth animation.
+#[derive(Clone, Copy, Debug)]
+pub struct Segment {
+    /// Position of the drawing symbol in the expanded command string.
+    pub index: usize,
+    /// Bracket depth at which the segment was drawn.
+    pub depth: u32,
+    /// Generation in which the segment's branch first appeared.
+    pub generation: u32,
+    pub width: f32,
+    /// Index into the grammar's palette.
+    pub color: usize,
+    /// The symbol that drew the segment.
+    pub symbol: char,
+    /// Length of the path drawn from the turtle's start to the segment.
+    pub distance: f32,
+    /// Index of the segment drawn just before this one on the same path.
+    pub parent: Option<usize>,
+    /// Which copy of the figure the segment belongs to, 0 for the original.
+    pub copy: u32,
+}
+
+/// A polyline drawn with a single width, coloured at each point.
+pub struct Stroke {
+    pub points: Vec<DVec3>,
+    pub width: f32,
+    pub colors: Vec<Paint>,
+}
+
+/// A connected polyline, with one `Segment` per pair of consecutive points.
+#[derive(Clone)]
+pub struct Section {
+    pub points: Vec<DVec3>,
+    pub segments: Vec<Segment>,
+    /// Index of the symbol after which the section starts, or `None` if it
+    /// starts at the turtle's initial position.
+    pub origin: Option<usize>,
+}
+
+impl Section {
+    fn new(start: DVec3, origin: Option<usize>) -> Section {
+        Section {
+            points: vec![start],
+            segments: Vec::new(),
+            origin,
+        }
+    }
+
+    /// Splits the section into strokes of constant width, given the colour
+    /// of each point and the width of each segment.
+    pub fn strokes(&self, colors: &[Paint], widths: &[f32]) -> Vec<Stroke> {
+        self.partial_strokes(colors, widths, |_| 1.0)
+    }
+
+    /// Like `strokes`, but draws only the fraction of each segment given by
+    /// `visible`. A partially visible segment ends its stroke.
+    pub fn partial_strokes(&self, colors: &[Paint], widths: &[f32], visible: impl Fn(usize) -> f32) -> Vec<Stroke> {
+        let mut strokes = Vec::new();
+        let mut current: Option<Stroke> = None;
+
+        for (i, &width) in widths.iter().enumerate() {
+            let shown = visible(i);
+            if shown <= 0.0 {
+                strokes.extend(current.take());
+                continue;
+            }
+            let (start, end) = (self.points[i], self.points[i + 1]);
+            let (start_color, end_color) = (colors[i], colors[i + 1]);
+            let (end, end_color) = if shown < 1.0 {
+                (start.lerp(end, shown as f64), start_color.lerp(end_color, shown))
+            } else {
+                (end, end_color)
+            };
+
+            match current.as_mut() {
+                Some(stroke) if stroke.width == width => {
+                    stroke.points.push(end);
+                    stroke.colors.push(end_color);
+                }
+                _ => {
+                    strokes.extend(current.take());
+                    current = Some(Stroke {
+                        points: vec![start, end],
+                        width,
+                        colors: vec![start_color, end_color],
+                    });
+                }
+            }
+            if shown < 1.0 {
+                strokes.extend(current.take());
+            }
+        }
+
+        strokes.extend(current);
+        strokes
+    }
+}
+
+/// A filled polygon whose vertices are recorded by `.` between `{` and `}`.
+#[derive(Clone)]
+pub struct Polygon {
+    pub points: Vec<DVec3>,
+    /// Index of the `.` symbol that recorded each point.
+    pub anchors: Vec<usize>,
+    /// Index into the grammar's palette.
+    pub color: usize,
+    /// Index of the `}` symbol that closed the polygon.
+    pub index: usize,
+    pub depth: u32,
+    pub generation: u32,
+    /// Which copy of the figure the polygon belongs to, 0 for the original.
+    pub copy: u32,
+}
+
+/// A predefined surface placed by `~` and the symbol after it, in the
+/// turtle's frame at that point.
+#[derive(Clone)]
+pub struct Stamp {
+    pub symbol: char,
+    pub position: DVec3,
+    pub heading: Vec3,
+    pub left: Vec3,
+    /// Step length when the surface was placed.
+    pub scale: f32,
+    /// Index into the grammar's palette.
+    pub color: usize,
+    /// Index of the surface's symbol.
+    pub index: usize,
+    pub depth: u32,
+    pub generation: u32,
+    /// Which copy of the figure the stamp belongs to, 0 for the original.
+    pub copy: u32,
+}
+
+/// The interpreted form of an expanded command string.
+pub struct Geometry {
+    pub sections: Vec<Section>,
+    pub polygons: Vec<Polygon>,
+    pub stamps: Vec<Stamp>,
+    /// Turtle position after each symbol of the command.
+    pub positions: Vec<DVec3>,
+    /// Turtle position before the first symbol.
+    pub start: DVec3,
+}
+
+impl Geometry {
+    /// The length of the diagonal of the box around the turtle's path.
+    pub fn size(&self) -> f64 {
+        let (min, max) = self.positions.iter().fold((self.start, self.start), |(min, max), p| (min.min(*p), max.max(*p)));
+        min.distance(max)
+    }
+
+    /// Scales everything about the turtle's starting point, as if drawn with
+    /// steps `factor` times as long.
+    fn rescale(&mut self, factor: f64) {
+        let start = self.start;
+        let scale = |p: &mut DVec3| *p = start + (*p - start) * factor;
+        for section in self.sections.iter_mut() {
+            section.points.iter_mut().for_each(scale);
+            section.segments.iter_mut().for_each(|segment| segment.distance *= factor as f32);
+        }
+        for polygon in self.polygons.iter_mut() {
+            polygon.points.iter_mut().for_each(scale);
+        }
+        for stamp in self.stamps.iter_mut() {
+            scale(&mut stamp.position);
+            stamp.scale *= factor as f32;
+        }
+        self.positions.iter_mut().for_each(scale);
+    }
+
+    /// The Strahler order of each segment of each section. Tips have order 1,
+    /// and where two or more paths of the highest order join, the order goes
+    /// up by one.
+    pub fn strahler_orders(&self) -> Vec<Vec<u32>> {
+        let mut segments: Vec<(usize, usize)> = self.sections.iter().enumerate()
+            .flat_map(|(s, section)| (0..section.segments.len()).map(move |i| (s, i)))
+            .collect();
+        // segments are drawn after their parents, and each copy of the figure
+        // has its own
+        let key = |s: usize, i: usize| {
+            let segment = &self.sections[s].segments[i];
+            (segment.copy, segment.index)
+        };
+        segments.sort_by_key(|&(s, i)| key(s, i));
+        let position: HashMap<(u32, usize), usize> = segments.iter().enumerate()
+            .map(|(k, &(s, i))| (key(s, i), k))
+            .collect();
+
+        // the highest order among each segment's children, and how many have it
+        let mut highest = vec![(0, 0); segments.len()];
+        let mut orders: Vec<Vec<u32>> = self.sections.iter().map(|section| vec![1; section.segments.len()]).collect();
+        for (k, &(s, i)) in segments.iter().enumerate().rev() {
+            let order = match highest[k] {
+                (0, _) => 1,
+                (max, count) if count >= 2 => max + 1,
+                (max, _) => max,
+            };
+            orders[s][i] = order;
+            let segment = &self.sections[s].segments[i];
+            if let Some(&parent) = segment.parent.and_then(|parent| position.get(&(segment.copy, parent))) {
+                let (max, count) = &mut highest[parent];
+                if order > *max {
+                    (*max, *count) = (order, 1);
+                } else if order == *max {
+                    *count += 1;
+                }
+            }
+        }
+        orders
+    }
+}
+
+/// An expanded command string with per-symbol bookkeeping.
+pub struct Expansion {
+    pub command: String,
+    /// Generation in which each symbol's branch first appeared.
+    pub generations: Vec<u32>,
+    /// Index of the symbol in the previous expansion that each symbol was
+    /// rewritten from.
+    pub parents: Vec<usize>,
+}
+
+impl Expansion {
+    fn new(start: &str) -> Expansion {
+        let len = start.chars().count();
+        Expansion {
+            command: start.to_owned(),
+            generations: vec![0; len],
+            parents: (0..len).collect(),
+        }
+    }
+}
+
+/// What `!` multiplies the line width by, and `#` divides it by, so that
+/// branches thin gradually however deep they go.
+const WIDTH_FACTOR: f32 = 0.7;
 
 impl LSystem {
-    pub fn new(start: &str, rules: HashMap<String, Vec<(String, u64)>>, length: u32, angle: f32, seed: u64) -> LSystem {
+    pub fn new(start: &str, rules: HashMap<String, Vec<(String, u64)>>, length: f32, angle: f32, seed: u64) -> LSystem {
         LSystem {
             start: start.to_string(),
             rules,
             length,
             angle,
+            tropism: Vec3::ZERO,
+            susceptibility: 0.0,
+            jitter: Jitter::default(),
+            symbol_jitter: HashMap::new(),
+            origin: Vec3::ZERO,
+            heading: 0.0,
+            step: 1.0,
+            length_factor: 1.0,
+            angle_factor: 1.0,
+            normalize: Normalize::None,
+            commands: default_commands().into_iter().collect(),
+            seed,
             rng: ChaCha12Rng::seed_from_u64(seed),
         }
     }
 
-    pub fn draw(&mut self, n: u32, scale: f32) -> Vec<Vec<Vec2>> {
-        let mut command = self.start.to_owned();
+    pub fn draw(&mut self, n: u32, scale: f32) -> Geometry {
+        let expansion = self.expand(n);
+        self.interpret(&expansion, n, scale)
+    }
+
+    /// Draws generation `n` partway through its transition into generation
+    /// `n + 1`. Each symbol of the next generation starts on the geometry of
+    /// the symbol it was rewritten from and moves to its own position as `t`
+    /// goes from 0 to 1.
+    pub fn draw_morph(&mut self, n: u32, t: f32, scale: f32) -> Geometry {
+        let t = t as f64;
+        let prev = self.expand(n);
+        let next = self.rewrite(&prev, n + 1);
+        let from = self.interpret(&prev, n, scale);
+        let mut to = self.interpret(&next, n + 1, scale);
+
+        let mut firsts = vec![0; prev.parents.len()];
+        let mut counts = vec![0; prev.parents.len()];
+        for (i, &parent) in next.parents.iter().enumerate().rev() {
+            firsts[parent] = i;
+            counts[parent] += 1;
+        }
+
+        // Where the turtle would be after symbol `i` if the next generation
+        // were laid out evenly along its parent's path.
+        let source = |i: usize| {
+            let parent = next.parents[i];
+            let before = if parent == 0 { from.start } else { from.positions[parent - 1] };
+            let after = from.positions[parent];
+            before.lerp(after, (i - firsts[parent] + 1) as f64 / counts[parent] as f64)
+        };
 
-        // println!("\n0: {}", command);
+        for section in to.sections.iter_mut() {
+            let mut anchors = vec![section.origin];
+            anchors.extend(section.segments.iter().map(|segment| Some(segment.index)));
+            for (point, anchor) in section.points.iter_mut().zip(anchors) {
+                let origin = anchor.map_or(from.start, source);
+                *point = origin.lerp(*point, t);
+            }
+        }
+        for polygon in to.polygons.iter_mut() {
+            for (point, &anchor) in polygon.points.iter_mut().zip(polygon.anchors.iter()) {
+                *point = source(anchor).lerp(*point, t);
+            }
+        }
+        for stamp in to.stamps.iter_mut() {
+            stamp.position = source(stamp.index).lerp(stamp.position, t);
+        }
+        to
+    }
 
-        for _ in 1..n + 1 {
-            command = self.rewrite(&command);
-            // println!("{}: {}", i, command);
+    /// Interprets generation `n`, normalized to the size of generation 0 if
+    /// `normalize` is set.
+    fn interpret(&self, expansion: &Expansion, n: u32, scale: f32) -> Geometry {
+        match self.normalize {
+            Normalize::None => self.calc_points(expansion, scale),
+            Normalize::Rules => self.trace(expansion, scale, 1.0 / self.growth_ratio().powi(n as i32) as f32, true),
+            Normalize::Bounds => {
+                let mut geometry = self.calc_points(expansion, scale);
+                let target = self.calc_points(&Expansion::new(&self.start), scale).size();
+                let size = geometry.size();
+                if target > 0.0 && size > 0.0 {
+                    geometry.rescale(target / size);
+                }
+                geometry
+            }
         }
+    }
+
+    /// How many times further the turtle gets with each generation: the
+    /// largest ratio, over the rules, of how far the replacement carries it
+    /// to how far the symbol alone does. Stochastic rules use their most
+    /// likely replacement. Jitter is left out, so that the ratio doesn't
+    /// depend on the seed.
+    fn growth_ratio(&self) -> f64 {
+        let reach = |command: &str| {
+            let geometry = self.trace(&Expansion::new(command), 1.0, 1.0, false);
+            geometry.positions.last().map_or(0.0, |end| end.distance(geometry.start))
+        };
+        self.rules.iter()
+            .filter_map(|(symbol, replacements)| {
+                let (replacement, _) = replacements.iter().max_by_key(|(_, weight)| *weight)?;
+                let before = reach(symbol);
+                (before > 0.0).then(|| reach(replacement) / before)
+            })
+            .filter(|ratio| *ratio > 0.0)
+            .reduce(f64::max)
+            .unwrap_or(1.0)
+    }
 
-        let points: Vec<Vec<Vec2>> = self.calc_points(&command, scale);
-        // println!("{:?}", points);
+    /// Rewrites the start string `n` times.
+    pub fn expand(&mut self, n: u32) -> Expansion {
+        let mut expansion = Expansion::new(&self.start);
 
-        points
+        for i in 1..n + 1 {
+            expansion = self.rewrite(&expansion, i);
+        }
+
+        expansion
     }
 
-    pub fn rewrite(&mut self, input: &String) -> String {
+    /// Applies one round of rules. Symbols at the top level of a replacement
+    /// continue their parent's branch and inherit its generation; anything
+    /// inside brackets is new growth and is tagged with `generation`. Symbols
+    /// naming a surface are never rewritten, and a rewritten symbol's
+    /// arguments are dropped.
+    pub fn rewrite(&mut self, input: &Expansion, generation: u32) -> Expansion {
         let mut res = String::from("");
-        for c in input.chars() {
+        let mut generations = Vec::new();
+        let mut parents = Vec::new();
+        let mut surface = false;
+        let chars: Vec<char> = input.command.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let (c, parent_generation) = (chars[i], input.generations[i]);
+            let end = argument_end(&chars, i).unwrap_or(i);
+            let is_surface = std::mem::take(&mut surface);
+            surface = !is_surface && self.commands.get(&c) == Some(&Action::Surface);
             match c {
-                c if self.rules.contains_key(&c.to_string()) => {
-                    res.push_str(&self.get_random_rewrite(&c.to_string()))
+                c if !is_surface && self.rules.contains_key(&c.to_string()) => {
+                    let replacement = self.get_random_rewrite(&c.to_string());
+                    let mut depth = 0;
+                    for r in replacement.chars() {
+                        let action = self.commands.get(&r);
+                        if action == Some(&Action::Pop) {
+                            depth -= 1;
+                        }
+                        generations.push(if depth > 0 { generation } else { parent_generation });
+                        parents.push(i);
+                        if action == Some(&Action::Push) {
+                            depth += 1;
+                        }
+                    }
+                    res.push_str(&replacement);
                 },
-                _ => res.push(c)
+                _ => {
+                    res.extend(&chars[i..=end]);
+                    generations.extend_from_slice(&input.generations[i..=end]);
+                    parents.extend(i..=end);
+                }
             }
+            i = end + 1;
+        }
+        Expansion {
+            command: res,
+            generations,
+            parents,
         }
-        res
     }
 
     fn get_random_rewrite(&mut self, key: &String) -> String {
         if self.rules.contains_key(&key.to_string()) {
             let rules = self.rules.get(&key.to_string()).unwrap();
-            rules.choose_weighted(&mut self.rng, |item| item.1).unwrap().0.clone()
+            // with every weight 0 the symbol is left as it is
+            match rules.choose_weighted(&mut self.rng, |item| item.1) {
+                Ok((replacement, _)) => replacement.clone(),
+                Err(_) => key.clone(),
+            }
         } else {
             String::from("")
         }
     }
 
-    pub fn calc_points(&self, input: &String, scale: f32) -> Vec<Vec<Vec2>> {
+    fn jitter_for(&self, c: char) -> Jitter {
+        self.symbol_jitter.get(&c).copied().unwrap_or(self.jitter)
+    }
+
+    fn turn(&self, jitter: Jitter, argument: Option<f32>, turtle: &Turtle, rng: &mut ChaCha12Rng) -> f32 {
+        argument.unwrap_or(self.angle) * turtle.angle_scale() + jitter.spread.sample(rng, jitter.angle)
+    }
+
+    fn step(&self, jitter: Jitter, argument: Option<f32>, scale: f32, turtle: &Turtle, rng: &mut ChaCha12Rng) -> f32 {
+        let length = argument.unwrap_or(self.length) * scale * turtle.step_scale();
+        (length * (1.0 + jitter.spread.sample(rng, jitter.length))).max(0.0)
+    }
+
+    /// Interprets an expanded command string. A symbol may be followed by an
+    /// argument list like `(15)`, whose first number replaces the amount the
+    /// command uses: the angle of a turn, the length of a step, the width `!`
+    /// sets or `#` adds, the factor of `"` and `;`, or the size of a
+    /// surface. With an argument, `'` selects that palette colour.
+    pub fn calc_points(&self, input: &Expansion, scale: f32) -> Geometry {
+        self.trace(input, scale, 1.0, true)
+    }
+
+    /// Interprets `input` with every step `step` times as long, leaving the
+    /// origin where it is, and with jitter only if `jittered`.
+    fn trace(&self, input: &Expansion, scale: f32, step: f32, jittered: bool) -> Geometry {
+        let jitter = |c: char| if jittered { self.jitter_for(c) } else { Jitter::default() };
+        // Jitter is drawn from its own stream of the seed so that it doesn't
+        // depend on how many rewrites came before.
+        let mut rng = ChaCha12Rng::seed_from_u64(self.seed);
+        rng.set_stream(1);
+
         let mut turtle = Turtle::new();
-        let mut points = vec![vec![turtle.curr()]];
+        turtle.jump(self.origin.as_f64() * scale as f64);
+        turtle.left(self.heading);
+        turtle.scale_step(self.step * step);
+        let start = turtle.curr();
+        let mut sections = vec![Section::new(start, None)];
+        let mut positions = Vec::with_capacity(input.generations.len());
+        let mut pushed = Vec::new();
+        // the last segment drawn on the current path and the path's length
+        let mut path: (Option<usize>, f32) = (None, 0.0);
+        let mut paths = Vec::new();
+        let mut polygons = Vec::new();
+        let mut open_polygons: Vec<Polygon> = Vec::new();
+        let mut stamps = Vec::new();
+        let mut surface = false;
 
-        for c in input.chars() {
-            match c {
-                c if c.is_ascii_uppercase() => {
-                    points.last_mut().unwrap().push(turtle.fd(self.length as f32 * scale))
-                }
-                c if c.is_ascii_lowercase() => {
-                    turtle.fd(self.length as f32 * scale);
+        let chars: Vec<char> = input.command.chars().collect();
+        let mut index = 0;
+        while index < chars.len() {
+            let (c, generation) = (chars[index], input.generations[index]);
+            let end = argument_end(&chars, index);
+            let argument = end
+                .and_then(|end| parse_arguments(&chars[index + 2..end]))
+                .and_then(|args| args.first().copied());
+
+            if std::mem::take(&mut surface) {
+                let (heading, left) = turtle.orientation();
+                stamps.push(Stamp {
+                    symbol: c,
+                    position: turtle.curr(),
+                    heading,
+                    left,
+                    scale: self.length * scale * turtle.step_scale() * argument.unwrap_or(1.0),
+                    color: turtle.color(),
+                    index,
+                    depth: pushed.len() as u32,
+                    generation,
+                    copy: 0,
+                });
+            } else {
+                match self.commands.get(&c).copied().unwrap_or(Action::NoOp) {
+                    Action::Surface => surface = true,
+                    Action::Draw => {
+                        let section = sections.last_mut().unwrap();
+                        let step = self.step(jitter(c), argument, scale, &turtle, &mut rng);
+                        section.points.push(turtle.fd(step));
+                        section.segments.push(Segment {
+                            index,
+                            depth: pushed.len() as u32,
+                            generation,
+                            width: turtle.width(),
+                            color: turtle.color(),
+                            symbol: c,
+                            distance: path.1,
+                            parent: path.0,
+                            copy: 0,
+                        });
+                        path = (Some(index), path.1 + step);
+                        if self.susceptibility != 0.0 {
+                            turtle.bend(self.tropism, self.susceptibility);
+                        }
+                    }
+                    Action::Move => {
+                        let step = self.step(jitter(c), argument, scale, &turtle, &mut rng);
+                        let pos = turtle.fd(step);
+                        // lift the pen, reusing the current section if nothing was
+                        // drawn since it started
+                        let section = Section::new(pos, Some(index));
+                        match sections.last_mut() {
+                            Some(last) if last.segments.is_empty() => *last = section,
+                            _ => sections.push(section),
+                        }
+                    }
+                    Action::TurnLeft => turtle.left(self.turn(jitter(c), argument, &turtle, &mut rng)),
+                    Action::TurnRight => turtle.right(self.turn(jitter(c), argument, &turtle, &mut rng)),
+                    Action::PitchDown => turtle.pitch_down(self.turn(jitter(c), argument, &turtle, &mut rng)),
+                    Action::PitchUp => turtle.pitch_up(self.turn(jitter(c), argument, &turtle, &mut rng)),
+                    Action::RollLeft => turtle.roll_left(self.turn(jitter(c), argument, &turtle, &mut rng)),
+                    Action::RollRight => turtle.roll_right(self.turn(jitter(c), argument, &turtle, &mut rng)),
+                    Action::TurnAround => turtle.turn_around(),
+                    Action::Thinner => turtle.set_width(match argument {
+                        Some(width) => width.max(0.0),
+                        None => turtle.width() * WIDTH_FACTOR,
+                    }),
+                    Action::Thicker => turtle.set_width(match argument {
+                        Some(width) => turtle.width() + width,
+                        None => turtle.width() / WIDTH_FACTOR,
+                    }),
+                    Action::NextColor => match argument {
+                        Some(color) => turtle.set_color(color.max(0.0) as usize),
+                        None => turtle.next_color(),
+                    },
+                    Action::ScaleStep => turtle.scale_step(argument.unwrap_or(self.length_factor)),
+                    Action::ScaleAngle => turtle.scale_angle(argument.unwrap_or(self.angle_factor)),
+                    Action::Push => {
+                        turtle.push();
+                        pushed.push(index);
+                        paths.push(path);
+                    },
+                    Action::Pop => match turtle.pop() {
+                        Ok(pos) => {
+                            sections.push(Section::new(pos, pushed.pop()));
+                            path = paths.pop().unwrap_or(path);
+                        },
+                        Err(err) => println!("{}", err),
+                    },
+                    Action::BeginPolygon => open_polygons.push(Polygon {
+                        points: Vec::new(),
+                        anchors: Vec::new(),
+                        color: turtle.color(),
+                        index,
+                        depth: pushed.len() as u32,
+                        generation,
+                        copy: 0,
+                    }),
+                    Action::Vertex => if let Some(polygon) = open_polygons.last_mut() {
+                        polygon.points.push(turtle.curr());
+                        polygon.anchors.push(index);
+                    },
+                    Action::EndPolygon => match open_polygons.pop() {
+                        Some(mut polygon) => {
+                            polygon.index = index;
+                            if polygon.points.len() >= 3 {
+                                polygons.push(polygon);
+                            }
+                        },
+                        None => println!("Malformed command: no polygon to close!"),
+                    },
+                    Action::NoOp => {},
                 }
-                '+' => turtle.left(self.angle),
-                '-' => turtle.right(self.angle),
-                '[' => turtle.push(),
-                ']' => match turtle.pop() {
-                    Ok(pos) => points.push(vec![pos]),
-                    Err(err) => println!("{}", err),
-                },
-                _ => {},
             }
+            // the arguments take the turtle's position after the command
+            let last = end.unwrap_or(index);
+            positions.extend(std::iter::repeat_n(turtle.curr(), last - index + 1));
+            index = last + 1;
         }
-        points
+
+        Geometry {
+            sections,
+            polygons,
+            stamps,
+            positions,
+            start,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn koch(seed: u64) -> LSystem {
+        let rules = HashMap::from([("F".to_string(), vec![("F+F--F+F".to_string(), 1)])]);
+        LSystem::new("F", rules, 9.0, 60.0, seed)
+    }
+
+    fn end(geometry: &Geometry) -> DVec3 {
+        *geometry.positions.last().unwrap()
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn rewrite_tags_branches_with_their_generation() {
+        let rules = HashMap::from([("X".to_string(), vec![("F[+X]".to_string(), 1)])]);
+        let mut lsystem = LSystem::new("F[X]", rules, 1.0, 45.0, 0);
+        let expansion = lsystem.rewrite(&Expansion::new(&lsystem.start), 1);
+        assert_eq!(expansion.command, "F[F[+X]]");
+        assert_eq!(expansion.generations, vec![0, 0, 0, 0, 1, 1, 0, 0]);
+        assert_eq!(expansion.parents, vec![0, 1, 2, 2, 2, 2, 2, 3]);
+        assert_eq!(lsystem.expand(2).command, "F[F[+F[+X]]]");
+    }
+
+    #[test]
+    fn rewrite_drops_arguments_of_rewritten_symbols() {
+        let rules = HashMap::from([("X".to_string(), vec![("F".to_string(), 1)])]);
+        let mut lsystem = LSystem::new("X(2)F(3)", rules, 1.0, 45.0, 0);
+        let expansion = lsystem.rewrite(&Expansion::new(&lsystem.start), 1);
+        assert_eq!(expansion.command, "FF(3)");
+        assert_eq!(expansion.parents, vec![0, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn zero_weights_leave_the_symbol() {
+        let rules = HashMap::from([("X".to_string(), vec![("FX".to_string(), 0)])]);
+        let mut lsystem = LSystem::new("X", rules, 1.0, 45.0, 0);
+        assert_eq!(lsystem.expand(2).command, "X");
+    }
+
+    #[test]
+    fn arguments_override_amounts() {
+        let lsystem = LSystem::new("F(2)+(90)F!(3)F!F", HashMap::new(), 1.0, 45.0, 0);
+        let geometry = lsystem.calc_points(&Expansion::new(&lsystem.start), 1.0);
+        let section = &geometry.sections[0];
+        assert!((section.points[1] - DVec3::new(2.0, 0.0, 0.0)).length() < 1e-6);
+        assert!((section.points[2] - DVec3::new(2.0, 1.0, 0.0)).length() < 1e-6);
+        let widths: Vec<f32> = section.segments.iter().map(|segment| segment.width).collect();
+        assert_eq!(widths[2], 3.0);
+        assert!(widths[3] < 3.0);
+    }
+
+    #[test]
+    fn widths_thin_gradually() {
+        let lsystem = LSystem::new("F!F!F#F", HashMap::new(), 1.0, 45.0, 0);
+        let geometry = lsystem.calc_points(&Expansion::new(&lsystem.start), 1.0);
+        let widths: Vec<f32> = geometry.sections[0].segments.iter().map(|segment| segment.width).collect();
+        assert_eq!(widths[0], 1.0);
+        assert!((widths[1] - 0.7).abs() < 1e-6);
+        assert!((widths[2] - 0.49).abs() < 1e-6);
+        assert!((widths[3] - 0.7).abs() < 1e-6);
+    }
+
+    #[test]
+    fn growth_ratio_ignores_jitter() {
+        let mut a = koch(1);
+        let mut b = koch(2);
+        for lsystem in [&mut a, &mut b] {
+            lsystem.jitter = Jitter { length: 0.3, angle: 10.0, ..Jitter::default() };
+        }
+        assert!((a.growth_ratio() - 3.0).abs() < 1e-9);
+        assert_eq!(a.growth_ratio(), b.growth_ratio());
+    }
+
+    #[test]
+    fn rules_normalization_keeps_origin_and_reach() {
+        let mut lsystem = koch(0);
+        lsystem.origin = Vec3::new(10.0, 5.0, 0.0);
+        lsystem.normalize = Normalize::Rules;
+        for n in 0..4 {
+            let geometry = lsystem.draw(n, 1.0);
+            assert_eq!(geometry.start, DVec3::new(10.0, 5.0, 0.0));
+            assert!((end(&geometry) - geometry.start - DVec3::new(9.0, 0.0, 0.0)).length() < 1e-4, "{:?}", end(&geometry));
+        }
+    }
+
+    #[test]
+    fn bounds_normalization_scales_about_start() {
+        let rules = HashMap::from([
+            ("X".to_string(), vec![("X+YF+".to_string(), 1)]),
+            ("Y".to_string(), vec![("-FX-Y".to_string(), 1)]),
+        ]);
+        let mut lsystem = LSystem::new("FX", rules, 10.0, 90.0, 0);
+        lsystem.origin = Vec3::new(-20.0, 30.0, 0.0);
+        lsystem.normalize = Normalize::Bounds;
+        let first = lsystem.draw(0, 1.0);
+        for n in [3, 6] {
+            let geometry = lsystem.draw(n, 1.0);
+            assert_eq!(geometry.start, first.start);
+            assert!((geometry.size() - first.size()).abs() < 1e-9);
+            assert_eq!(geometry.sections[0].points[0], first.start);
+        }
+    }
+}
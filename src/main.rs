@@ -1,12 +1,60 @@
 use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
 
-use lsystem::LSystem;
+use batch::{LineBatch, Visible};
+use camera::Camera;
+use canvas::{Canvas, Pacing, View};
+use coloring::{ColorMode, Coloring};
+use commands::{default_commands, Action};
+use grammar::{LSystemInput, Preset};
+use jitter::{Jitter, Spread};
+use growth::GrowthOrder;
+use lines::{LineRenderer, Lines, Pass, Transform};
+use lsystem::{Geometry, LSystem};
+use mesh::Mesh;
+use scene::Shape;
+use smoothing::{SmoothMode, Smoothing};
+use spatial::Bounds;
+use surface::{Surface, SurfaceKind};
+use symmetry::Symmetry;
+use taper::{Taper, TaperMode};
+use trail::{Accumulator, BlendMode, Trail};
 use nannou::prelude::*;
+use nannou::glam::DVec2;
+use nannou::wgpu;
 use rand::prelude::random;
 use nannou_egui::{self, egui::{self, epaint::Shadow, Align2, Color32, ComboBox, RichText, Rounding, TextFormat}, Egui};
+use normalize::Normalize;
+use modulation::{Modulation, Route, Source, Waveform};
+use timeline::{Easing, Keyframe, Param, Timeline};
 
 mod turtle;
 mod lsystem;
+mod normalize;
+mod growth;
+mod grammar;
+mod timeline;
+mod modulation;
+mod batch;
+mod lines;
+mod camera;
+mod canvas;
+mod mesh;
+mod export;
+mod coloring;
+mod commands;
+mod jitter;
+mod scene;
+mod smoothing;
+mod spatial;
+mod surface;
+mod symmetry;
+mod taper;
+mod trail;
 
 macro_rules! str {
     () => {
@@ -17,48 +65,203 @@ macro_rules! str {
     };
 }
 
+#[derive(Clone)]
 struct Settings {
-    scale: f32,
+    scale: f64,
     rotation: f32,
-    offset: Vec2,
+    /// Where the origin appears, in pixels from the window's centre.
+    offset: DVec2,
     level: u32,
-    speed: f32,
     angle: f32,
-    length: u32,
-    animate_angle: bool,
-    clear_bg: bool,
+    length: f32,
+    normalize: Normalize,
+    trail: Trail,
+    /// Seconds a frame may take before figures are drawn over several.
+    frame_budget: f32,
+    /// Whether a figure drawn over several frames is only shown once done.
+    hold_drawing: bool,
     default_preset: String,
     variables_buffer: String,
     new_rule_buffer: (String, String, u64),
     seed: u64,
     hide_ui: bool,
-}
-
-#[derive(Clone)]
-struct LSystemInput {
-    variables: Vec<String>,
-    rules: Vec<(String, String, u64)>,
-    start: String,
-}
-
-struct Preset {
-    lsystem: LSystemInput,
-    level: u32,
-    angle: f32,
-    length: u32,
+    grow: bool,
+    grow_order: GrowthOrder,
+    grow_progress: f32,
+    grow_speed: f32,
+    grow_playing: bool,
+    morph: bool,
+    morph_speed: f32,
+    morph_level: f32,
+    cycle_color: bool,
+    hue: f32,
+    saturation: f32,
+    brightness: f32,
+    file_path: String,
+    new_key_buffer: (Param, Easing),
+    record_fps: f32,
+    camera: Camera,
+    mesh_radius: f32,
+    mesh_sides: usize,
+    tropism: Vec3,
+    susceptibility: f32,
+    coloring: Coloring,
+    taper: Taper,
+    smoothing: Smoothing,
+    symmetry: Symmetry,
 }
 
 struct Drag {
     is_dragging: bool,
+    is_orbiting: bool,
     start_pos: Vec2,
 }
 
+struct Recording {
+    name: String,
+    frame: u32,
+}
+
 struct Model {
     drag_event: Drag,
     settings: Settings,
     egui: Egui,
     lsys_input: LSystemInput,
     presets: HashMap<String, Preset>,
+    timeline: Timeline,
+    modulation: Modulation,
+    recording: Option<Recording>,
+    clock: f32,
+    /// Sprite images by path, or `None` if the image failed to load.
+    sprites: HashMap<String, Option<wgpu::Texture>>,
+    figure: Figure,
+    /// Where the figure is drawn when it is too big to draw in one frame.
+    canvas: Option<Canvas>,
+    pacing: Pacing,
+    /// What the window shows while earlier frames linger.
+    accumulator: Option<Accumulator>,
+    lines: LineRenderer,
+}
+
+/// Everything the expanded figure depends on.
+#[derive(Clone, PartialEq)]
+struct GeometryKey {
+    lsys_input: LSystemInput,
+    length: f32,
+    normalize: Normalize,
+    angle: f32,
+    seed: u64,
+    tropism: Vec3,
+    susceptibility: f32,
+    level: f32,
+    /// Without the hue shift, which only changes the colours.
+    symmetry: Symmetry,
+}
+
+/// Everything the figure's shapes depend on besides its geometry.
+#[derive(Clone, PartialEq)]
+struct ShapesKey {
+    coloring: Coloring,
+    taper: Taper,
+    smoothing: Smoothing,
+    growth: Option<(GrowthOrder, f32)>,
+    camera: Camera,
+    hue_shift: f32,
+    /// Only set while colours are animated.
+    clock: f32,
+}
+
+/// The figure as last built. It is rebuilt in the background whenever
+/// something it depends on changes, and shown as it was until the new one is
+/// ready. Panning, rotating and zooming reuse it as is.
+#[derive(Default)]
+struct Figure {
+    geometry: Option<(GeometryKey, Arc<Geometry>)>,
+    shapes: Option<(ShapesKey, Vec<Shape>, LineBatch, Rc<Lines>)>,
+    /// Counts up each time the shapes are rebuilt.
+    version: u64,
+    /// The lines in the window, culled once per update.
+    visible: Visible,
+    /// The build running in the background, if any, and what it is for.
+    building: Option<((GeometryKey, ShapesKey), Receiver<Build>)>,
+    /// What the last build that failed was for, so it isn't tried again.
+    failed: Option<(GeometryKey, ShapesKey)>,
+}
+
+/// A figure built in the background.
+struct Build {
+    geometry: (GeometryKey, Arc<Geometry>),
+    shapes: (ShapesKey, Vec<Shape>, LineBatch),
+}
+
+impl Figure {
+    /// Takes in the figure built in the background if it is done, then starts
+    /// building it again if the settings have changed since. Only one build
+    /// runs at a time, and it is always for the latest settings.
+    fn refresh(&mut self, device: &wgpu::Device, settings: &Settings, lsys_input: &LSystemInput, clock: f32) {
+        if let Some((keys, building)) = &self.building {
+            match building.try_recv() {
+                Ok(Build { geometry, shapes: (key, shapes, batch) }) => {
+                    let lines = Rc::new(Lines::new(device, &batch));
+                    self.geometry = Some(geometry);
+                    self.shapes = Some((key, shapes, batch, lines));
+                    self.version += 1;
+                    self.building = None;
+                }
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.failed = Some(keys.clone());
+                    self.building = None;
+                }
+            }
+        }
+
+        let geometry_key = GeometryKey {
+            lsys_input: lsys_input.clone(),
+            length: settings.length,
+            normalize: settings.normalize,
+            angle: settings.angle,
+            seed: settings.seed,
+            tropism: settings.tropism,
+            susceptibility: settings.susceptibility,
+            level: settings.morph_level,
+            symmetry: Symmetry { hue_shift: 0.0, ..settings.symmetry },
+        };
+        let shapes_key = ShapesKey {
+            coloring: settings.coloring.clone(),
+            taper: settings.taper,
+            smoothing: settings.smoothing,
+            growth: settings.grow.then_some((settings.grow_order, settings.grow_progress)),
+            camera: settings.camera,
+            hue_shift: settings.symmetry.hue_shift,
+            clock: if settings.coloring.animate { clock } else { 0.0 },
+        };
+        let geometry = match &self.geometry {
+            Some((key, geometry)) if *key == geometry_key => {
+                if self.shapes.as_ref().is_some_and(|(key, ..)| *key == shapes_key) {
+                    return;
+                }
+                Some(geometry.clone())
+            }
+            _ => None,
+        };
+        let keys = (geometry_key, shapes_key);
+        if self.failed.as_ref() == Some(&keys) {
+            return;
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let (settings, lsys_input) = (settings.clone(), lsys_input.clone());
+        let (geometry_key, shapes_key) = keys.clone();
+        thread::spawn(move || {
+            let geometry = geometry.unwrap_or_else(|| Arc::new(build_geometry(&settings, &lsys_input)));
+            let shapes = build_shapes(&geometry, &settings, &lsys_input, clock);
+            let batch = LineBatch::new(&shapes, CURVE_STEPS);
+            // the receiver is gone if the app has closed
+            let _ = sender.send(Build { geometry: (geometry_key, geometry), shapes: (shapes_key, shapes, batch) });
+        });
+        self.building = Some((keys, receiver));
+    }
 }
 
 fn main() {
@@ -79,12 +282,13 @@ fn model(app: &App) -> Model {
 
     let window = &app.window(window_id).unwrap();
 
-    let egui = Egui::from_window(&window);
+    let egui = Egui::from_window(window);
+    let lines = LineRenderer::new(window.device(), &[window.msaa_samples(), 1]);
 
     let presets: HashMap<String, Preset> = HashMap::from([
         ("sierpinsky".to_string(), Preset {
             level: 6,
-            length: 3,
+            length: 3.0,
             angle: 120.0,
             lsystem: LSystemInput {
             start: "F-G-G".to_string(),
@@ -93,10 +297,13 @@ fn model(app: &App) -> Model {
                 ("G".to_string(), "GG".to_string(), 1),
             ],
             variables: vec!["F".to_string(), 'G'.to_string()],
-        }}),
+            ..Default::default()
+            },
+            ..Default::default()
+        }),
         ("dragon".to_string(), Preset {
             level: 12,
-            length: 10,
+            length: 10.0,
             angle: 90.0,
             lsystem: LSystemInput {
             start: "F".to_string(),
@@ -105,10 +312,13 @@ fn model(app: &App) -> Model {
                 ("G".to_string(), "F-G".to_string(), 1),
             ],
             variables: vec![str!('F'), str!('G')],
-        }}),
+            ..Default::default()
+            },
+            ..Default::default()
+        }),
         ("plant".to_string(), Preset {
             level: 6,
-            length: 10,
+            length: 10.0,
             angle: 25.0,
             lsystem: LSystemInput {
             start: "f".to_string(),
@@ -117,10 +327,14 @@ fn model(app: &App) -> Model {
                 ("F".to_string(), "FF".to_string(), 1),
             ],
             variables: vec![str!('f'), str!('F')],
-        }}),
+            heading: 90.0,
+            ..Default::default()
+            },
+            ..Default::default()
+        }),
         ("binary tree".to_string(), Preset {
             level: 6,
-            length: 10,
+            length: 10.0,
             angle: 45.0,
             lsystem: LSystemInput {
             start: "A".to_string(),
@@ -129,10 +343,14 @@ fn model(app: &App) -> Model {
                 ("B".to_string(), "BB".to_string(), 1),
             ],
             variables: vec![str!('A'), str!('B')],
-        }}),
+            heading: 90.0,
+            ..Default::default()
+            },
+            ..Default::default()
+        }),
         ("stochastic plant".to_string(), Preset {
             level: 6,
-            length: 10,
+            length: 10.0,
             angle: 25.0,
             lsystem: LSystemInput {
             start: "F".to_string(),
@@ -142,7 +360,11 @@ fn model(app: &App) -> Model {
                 ("F".to_string(), "F[-F]F".to_string(), 1),
             ],
             variables: vec![str!('F')],
-        }}),
+            heading: 90.0,
+            ..Default::default()
+            },
+            ..Default::default()
+        }),
     ]);
 
     let default_preset = "stochastic plant".to_string();
@@ -152,26 +374,278 @@ fn model(app: &App) -> Model {
         egui,
         drag_event: Drag {
             is_dragging: false,
+            is_orbiting: false,
             start_pos: pt2(0.0, 0.0),
         },
         settings: Settings {
             scale: 1.0,
             rotation: 0.0,
-            offset: pt2(0.0, 0.0),
+            offset: DVec2::ZERO,
             level: preset.level,
-            speed: 5.0,
             angle: preset.angle,
             length: preset.length,
-            animate_angle: false,
-            clear_bg: true,
+            normalize: preset.normalize,
+            trail: preset.trail,
+            frame_budget: 1.0 / 30.0,
+            hold_drawing: false,
             default_preset,
             variables_buffer: String::from(""),
             new_rule_buffer: ("".to_string(), "".to_string(), 1),
             seed: random(),
             hide_ui: false,
+            grow: false,
+            grow_order: GrowthOrder::String,
+            grow_progress: 1.0,
+            grow_speed: 1.0,
+            grow_playing: false,
+            morph: false,
+            morph_speed: 1.0,
+            morph_level: preset.level as f32,
+            cycle_color: true,
+            hue: 0.0,
+            saturation: 0.75,
+            brightness: 0.9,
+            file_path: "lsystem.txt".to_string(),
+            new_key_buffer: (Param::Angle, Easing::Linear),
+            record_fps: 30.0,
+            camera: Camera::default(),
+            mesh_radius: 0.5,
+            mesh_sides: 8,
+            tropism: preset.tropism,
+            susceptibility: preset.susceptibility,
+            coloring: preset.coloring.clone(),
+            taper: preset.taper,
+            smoothing: preset.smoothing,
+            symmetry: preset.symmetry,
         },
         lsys_input: preset.lsystem.clone(),
+        timeline: preset.timeline.clone(),
+        modulation: preset.modulation.clone(),
         presets,
+        recording: None,
+        clock: 0.0,
+        sprites: HashMap::new(),
+        figure: Figure::default(),
+        canvas: None,
+        pacing: Pacing::default(),
+        accumulator: None,
+        lines,
+    }
+}
+
+fn apply_preset(preset: &Preset, settings: &mut Settings, lsys_input: &mut LSystemInput, timeline: &mut Timeline, modulation: &mut Modulation) {
+    *lsys_input = preset.lsystem.clone();
+    *timeline = preset.timeline.clone();
+    *modulation = preset.modulation.clone();
+    settings.angle = preset.angle;
+    settings.length = preset.length;
+    settings.normalize = preset.normalize;
+    settings.level = preset.level;
+    settings.tropism = preset.tropism;
+    settings.susceptibility = preset.susceptibility;
+    settings.coloring = preset.coloring.clone();
+    settings.taper = preset.taper;
+    settings.smoothing = preset.smoothing;
+    settings.symmetry = preset.symmetry;
+    settings.trail = preset.trail;
+}
+
+fn current_preset(settings: &Settings, lsys_input: &LSystemInput, timeline: &Timeline, modulation: &Modulation) -> Preset {
+    Preset {
+        lsystem: lsys_input.clone(),
+        level: settings.level,
+        angle: settings.angle,
+        length: settings.length,
+        normalize: settings.normalize,
+        tropism: settings.tropism,
+        susceptibility: settings.susceptibility,
+        coloring: settings.coloring.clone(),
+        taper: settings.taper,
+        smoothing: settings.smoothing,
+        symmetry: settings.symmetry,
+        trail: settings.trail,
+        timeline: timeline.clone(),
+        modulation: modulation.clone(),
+    }
+}
+
+fn param_value(settings: &Settings, lsys_input: &LSystemInput, param: Param) -> f32 {
+    match param {
+        Param::Angle => settings.angle,
+        Param::Length => settings.length,
+        Param::Rotation => settings.rotation,
+        Param::Scale => settings.scale as f32,
+        Param::OffsetX => settings.offset.x as f32,
+        Param::OffsetY => settings.offset.y as f32,
+        Param::Level => settings.level as f32,
+        Param::Hue => settings.hue,
+        Param::Saturation => settings.saturation,
+        Param::Brightness => settings.brightness,
+        Param::TropismX => settings.tropism.x,
+        Param::TropismY => settings.tropism.y,
+        Param::TropismZ => settings.tropism.z,
+        Param::Susceptibility => settings.susceptibility,
+        Param::Heading => lsys_input.heading,
+        Param::Step => lsys_input.step,
+        Param::LengthFactor => lsys_input.length_factor,
+        Param::AngleFactor => lsys_input.angle_factor,
+        Param::JitterAngle => lsys_input.jitter.angle,
+        Param::JitterLength => lsys_input.jitter.length,
+        Param::GrowSpeed => settings.grow_speed,
+        Param::MorphSpeed => settings.morph_speed,
+        Param::TrailFade => settings.trail.fade,
+        Param::TaperBase => settings.taper.base,
+        Param::TaperRatio => settings.taper.ratio,
+        Param::TaperMin => settings.taper.min,
+        Param::RuleWeight(idx) => lsys_input.rules.get(idx).map_or(0.0, |rule| rule.2 as f32),
+    }
+}
+
+fn set_param(settings: &mut Settings, lsys_input: &mut LSystemInput, param: Param, value: f32) {
+    match param {
+        Param::Angle => settings.angle = value,
+        Param::Length => settings.length = value.max(0.0),
+        Param::Rotation => settings.rotation = value,
+        Param::Scale => settings.scale = value as f64,
+        Param::OffsetX => settings.offset.x = value as f64,
+        Param::OffsetY => settings.offset.y = value as f64,
+        Param::Level => settings.level = value.round().clamp(0.0, 20.0) as u32,
+        Param::Hue => settings.hue = value,
+        Param::Saturation => settings.saturation = value,
+        Param::Brightness => settings.brightness = value,
+        Param::TropismX => settings.tropism.x = value,
+        Param::TropismY => settings.tropism.y = value,
+        Param::TropismZ => settings.tropism.z = value,
+        Param::Susceptibility => settings.susceptibility = value,
+        Param::Heading => lsys_input.heading = value,
+        Param::Step => lsys_input.step = value.max(0.0),
+        Param::LengthFactor => lsys_input.length_factor = value.max(0.0),
+        Param::AngleFactor => lsys_input.angle_factor = value.max(0.0),
+        Param::JitterAngle => lsys_input.jitter.angle = value.clamp(0.0, 180.0),
+        Param::JitterLength => lsys_input.jitter.length = value.clamp(0.0, 1.0),
+        Param::GrowSpeed => settings.grow_speed = value.max(0.0),
+        Param::MorphSpeed => settings.morph_speed = value.max(0.0),
+        Param::TrailFade => settings.trail.fade = value.clamp(0.0, 1.0),
+        Param::TaperBase => settings.taper.base = value.max(0.0),
+        Param::TaperRatio => settings.taper.ratio = value.clamp(0.0, 1.0),
+        Param::TaperMin => settings.taper.min = value.max(0.0),
+        Param::RuleWeight(idx) => if let Some(rule) = lsys_input.rules.get_mut(idx) {
+            // a symbol whose weights all reach 0 would have nothing to pick
+            rule.2 = value.round().max(1.0) as u64;
+        },
+    }
+}
+
+fn build_lsystem(settings: &Settings, lsys_input: &LSystemInput) -> LSystem {
+    let mut rules: HashMap<String, Vec<(String, u64)>> = HashMap::new();
+
+    lsys_input.rules.iter().for_each(|(k, v, weight)| {
+        let r = rules.entry(k.to_string());
+        r.or_default().push((v.to_string(), *weight));
+    });
+
+    let mut lsystem = LSystem::new(
+        &lsys_input.start,
+        rules,
+        settings.length,
+        settings.angle,
+        settings.seed,
+    );
+    lsystem.tropism = settings.tropism;
+    lsystem.susceptibility = settings.susceptibility;
+    lsystem.jitter = lsys_input.jitter;
+    lsystem.symbol_jitter = lsys_input.symbol_jitter.iter().copied().collect();
+    lsystem.origin = lsys_input.origin;
+    lsystem.heading = lsys_input.heading;
+    lsystem.step = lsys_input.step;
+    lsystem.length_factor = lsys_input.length_factor;
+    lsystem.normalize = settings.normalize;
+    lsystem.angle_factor = lsys_input.angle_factor;
+    lsystem.commands = lsys_input.commands.iter().copied().collect();
+    lsystem
+}
+
+fn export_mesh(app: &App, settings: &Settings, lsys_input: &LSystemInput, clock: f32, extension: &str) {
+    let geometry = settings.symmetry.replicate(build_lsystem(settings, lsys_input).draw(settings.level, 1.0));
+    let mut colors = settings.coloring.colors(&geometry, &lsys_input.palette, clock);
+    settings.symmetry.tint(&geometry, &mut colors);
+    let base = base_color(settings, clock);
+    let colors: Vec<Vec<[f32; 3]>> = colors.sections.iter()
+        .map(|colors| colors.iter().map(|color| color.resolve(base)).collect())
+        .collect();
+    let widths = settings.taper.widths(&geometry);
+    let mesh = Mesh::tubes(&geometry.sections, &colors, &widths, settings.mesh_radius, settings.mesh_sides);
+
+    let timestamp = std::time::UNIX_EPOCH.elapsed().unwrap().as_secs();
+    let filename = format!("{}-{}.{}", app.exe_name().unwrap(), timestamp, extension);
+    let path = Path::new(&filename);
+    let res = match extension {
+        "obj" => export::write_obj(&mesh, path),
+        _ => export::write_glb(&mesh, path),
+    };
+    match res {
+        Ok(()) => println!("exported {} triangles: {}", mesh.indices.len() / 3, filename),
+        Err(err) => println!("{}", err),
+    }
+}
+
+/// The colour drawn for palette index 0, which is filled in as the figure is
+/// drawn rather than when it is built.
+fn base_color(settings: &Settings, clock: f32) -> [f32; 3] {
+    if settings.cycle_color {
+        coloring::hsv_to_rgb(
+            map_range((clock / 2.0).sin(), -1.0, 1.0, 0.0, 1.0),
+            map_range((clock / 3.0).cos(), -1.0, 1.0, 0.5, 1.0),
+            map_range((clock * 10.0).sin(), -1.0, 1.0, 0.8, 1.0),
+        )
+    } else {
+        coloring::hsv_to_rgb(settings.hue, settings.saturation, settings.brightness)
+    }
+}
+
+/// Expands the figure at the current, possibly morphing, level.
+fn build_geometry(settings: &Settings, lsys_input: &LSystemInput) -> Geometry {
+    let mut lsystem = build_lsystem(settings, lsys_input);
+
+    let level = settings.morph_level;
+    let geometry = if level.fract() > 0.0 {
+        // ease in and out of each generation
+        let t = level.fract();
+        lsystem.draw_morph(level.floor() as u32, t * t * (3.0 - 2.0 * t), 1.0)
+    } else {
+        lsystem.draw(level as u32, 1.0)
+    };
+    settings.symmetry.replicate(geometry)
+}
+
+/// Builds the figure as it appears on screen, before the global offset,
+/// rotation and zoom are applied.
+fn build_shapes(geometry: &Geometry, settings: &Settings, lsys_input: &LSystemInput, clock: f32) -> Vec<Shape> {
+    let mut colors = settings.coloring.colors(geometry, &lsys_input.palette, clock);
+    settings.symmetry.tint(geometry, &mut colors);
+    let widths = settings.taper.widths(geometry);
+    let (strokes, polygons, stamps) = if settings.grow {
+        growth::reveal(geometry, &colors.sections, &widths, settings.grow_order, settings.grow_progress)
+    } else {
+        let strokes = geometry.sections.iter().zip(colors.sections.iter().zip(widths.iter()))
+            .flat_map(|(section, (colors, widths))| section.strokes(colors, widths))
+            .collect();
+        (strokes, (0..geometry.polygons.len()).collect(), (0..geometry.stamps.len()).collect())
+    };
+
+    let polygons = polygons.into_iter().map(|idx| (&geometry.polygons[idx], colors.polygons[idx]));
+    let stamps = stamps.into_iter().map(|idx| (&geometry.stamps[idx], colors.stamps[idx]));
+    scene::shapes(strokes, polygons, stamps, &lsys_input.surfaces, &settings.camera, &settings.smoothing)
+}
+
+fn export_svg(app: &App, settings: &Settings, lsys_input: &LSystemInput, clock: f32) {
+    let geometry = build_geometry(settings, lsys_input);
+    let shapes = build_shapes(&geometry, settings, lsys_input, clock);
+    let timestamp = std::time::UNIX_EPOCH.elapsed().unwrap().as_secs();
+    let filename = format!("{}-{}.svg", app.exe_name().unwrap(), timestamp);
+    match export::write_svg(&shapes, base_color(settings, clock), settings.trail.background, settings.scale as f32, settings.rotation.to_radians(), Path::new(&filename)) {
+        Ok(()) => println!("exported svg: {}", filename),
+        Err(err) => println!("{}", err),
     }
 }
 
@@ -184,37 +658,91 @@ fn integer_edit_field(ui: &mut egui::Ui, value: &mut u64, width: f32) -> egui::R
         .show(ui).response;
     if let Ok(result) = tmp_value.parse() {
         *value = result;
-    } else if tmp_value == "" {
+    } else if tmp_value.is_empty() {
         *value = 0;
     }
     res
 }
 
-fn rules_error_highlighter(rule: &str, variables: Vec<String>) -> egui::text::LayoutJob {
+fn symbol_edit_field(ui: &mut egui::Ui, symbol: &mut char) -> egui::Response {
+    let mut tmp_value = symbol.to_string();
+    let res = ui.add(egui::TextEdit::singleline(&mut tmp_value).char_limit(1).desired_width(12.0));
+    if let Some(c) = tmp_value.chars().next() {
+        *symbol = c;
+    }
+    res
+}
+
+fn jitter_edit(ui: &mut egui::Ui, id: impl std::hash::Hash, jitter: &mut Jitter) {
+    ComboBox::from_id_source(id)
+        .selected_text(jitter.spread.name())
+        .show_ui(ui, |ui| {
+            ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+            for spread in Spread::ALL {
+                ui.selectable_value(&mut jitter.spread, spread, spread.name());
+            }
+        });
+    ui.add(egui::DragValue::new(&mut jitter.angle).speed(0.1).clamp_range(0.0..=180.0).prefix("angle ±").suffix("°"));
+    ui.add(egui::DragValue::new(&mut jitter.length).speed(0.01).clamp_range(0.0..=1.0).prefix("length ±"));
+}
+
+/// The symbols a command string may use, for highlighting.
+struct Symbols {
+    variables: Vec<String>,
+    surfaces: Vec<char>,
+    commands: HashMap<char, Action>,
+}
+
+fn rules_error_highlighter(rule: &str, symbols: &Symbols) -> egui::text::LayoutJob {
     use egui::text::LayoutJob;
     let mut job = LayoutJob::default();
 
     let error_color = Color32::RED;
-
-    for c in rule.chars() {
-        if !variables.contains(&c.to_string()) && !lsystem::VALID_CHARS.contains(&c) {
-            job.append(&c.to_string(), 0.0, TextFormat {
+    let mut append = |text: &str, valid: bool| {
+        let format = if valid {
+            TextFormat::default()
+        } else {
+            TextFormat {
                 color: error_color,
                 ..Default::default()
-            });
+            }
+        };
+        job.append(text, 0.0, format);
+    };
+
+    let chars: Vec<char> = rule.chars().collect();
+    let mut surface = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let valid = if std::mem::take(&mut surface) {
+            symbols.surfaces.contains(&c)
         } else {
-            job.append(&c.to_string(), 0.0, TextFormat {
-                ..Default::default()
-            });
+            surface = symbols.commands.get(&c) == Some(&Action::Surface);
+            // letters that draw, move or do nothing must be declared, so
+            // that typos show up
+            let declared = symbols.variables.contains(&c.to_string());
+            declared || symbols.commands.get(&c).is_some_and(|action| !matches!(action, Action::Draw | Action::Move | Action::NoOp))
+        };
+        append(&c.to_string(), valid);
+
+        // an argument list must hold only numbers
+        match commands::argument_end(&chars, i) {
+            Some(end) => {
+                let arguments: String = chars[i + 1..=end].iter().collect();
+                append(&arguments, commands::parse_arguments(&chars[i + 2..end]).is_some());
+                i = end + 1;
+            }
+            None => i += 1,
         }
     }
 
     job
 }
 
-fn rule_edit_field(ui: &mut egui::Ui, value: &mut String, variables: Vec<String>) -> egui::Response {
+fn rule_edit_field(ui: &mut egui::Ui, value: &mut String, symbols: &Symbols) -> egui::Response {
     let mut layouter  = |ui: &egui::Ui, string: &str, wrap_width: f32| {
-        let mut layout_job: egui::text::LayoutJob = rules_error_highlighter(string, variables.clone());
+        let mut layout_job: egui::text::LayoutJob = rules_error_highlighter(string, symbols);
         layout_job.wrap.max_width = wrap_width;
         ui.fonts(|f| f.layout_job(layout_job))
     };
@@ -238,14 +766,120 @@ fn update(app: &App, model: &mut Model, _update: Update) {
             .shadow(Shadow::NONE))
         .anchor(Align2::LEFT_TOP, [10.0, 10.0]);
 
-    if settings.animate_angle {
-        let sine = (app.time / map_range(settings.speed, 1.0, 10.0, 5.0, 1.0)).sin();
-        settings.angle = map_range(sine, -1.0, 1.0, 60.0, 100.0);
+    // while recording, time advances by exactly one frame per update so the
+    // exported sequence doesn't depend on how fast frames are rendered
+    let dt = match model.recording {
+        Some(_) => 1.0 / settings.record_fps,
+        None => _update.since_last.as_secs_f32(),
+    };
+    model.clock += dt;
+
+    let target_level = settings.level as f32;
+    if settings.morph {
+        let step = dt * settings.morph_speed;
+        if (target_level - settings.morph_level).abs() <= step {
+            settings.morph_level = target_level;
+        } else {
+            settings.morph_level += step * (target_level - settings.morph_level).signum();
+        }
+    } else {
+        settings.morph_level = target_level;
+    }
+
+    if settings.grow && settings.grow_playing {
+        settings.grow_progress += dt * settings.grow_speed / 10.0;
+        if settings.grow_progress >= 1.0 {
+            settings.grow_progress = 1.0;
+            settings.grow_playing = false;
+        }
+    }
+
+    let timeline = &mut model.timeline;
+    if timeline.playing {
+        for (param, value) in timeline.sample() {
+            set_param(settings, &mut model.lsys_input, param, value);
+        }
+    }
+
+    if let Some(recording) = &mut model.recording {
+        let filename = format!("{}-{:05}.png", recording.name, recording.frame);
+        app.main_window().capture_frame(filename);
+        recording.frame += 1;
+        if recording.frame as f32 >= timeline.duration * settings.record_fps {
+            println!("recorded {} frames", recording.frame);
+            model.recording = None;
+            timeline.playing = false;
+        }
+    }
+
+    if timeline.playing && !timeline.advance(dt) {
+        timeline.playing = false;
+    }
+
+    for (param, value) in model.modulation.sample(model.clock) {
+        set_param(settings, &mut model.lsys_input, param, value);
+    }
+
+    for surface in model.lsys_input.surfaces.iter() {
+        if let SurfaceKind::Sprite(image) = &surface.kind {
+            if !model.sprites.contains_key(image) {
+                let texture = match wgpu::Texture::from_path(app, image) {
+                    Ok(texture) => Some(texture),
+                    Err(err) => {
+                        println!("{}: {}", image, err);
+                        None
+                    }
+                };
+                model.sprites.insert(image.clone(), texture);
+            }
+        }
     }
 
     let used_vars: Vec<String> = model.lsys_input.rules.clone().into_iter().map(|(k, _, _)| k).collect();
+    let symbols = Symbols {
+        variables: model.lsys_input.variables.clone(),
+        surfaces: model.lsys_input.surfaces.iter().map(|surface| surface.symbol).collect(),
+        commands: model.lsys_input.commands.iter().copied().collect(),
+    };
 
-    if settings.hide_ui {
+    model.figure.refresh(app.main_window().device(), settings, &model.lsys_input, model.clock);
+    if let Some((_, _, batch, _)) = &model.figure.shapes {
+        model.figure.visible = batch.visible(&figure_bounds(app.window_rect(), settings), view_centre(settings), settings.scale);
+    }
+
+    // figures that take too long to draw in one frame are drawn over several,
+    // except while recording, where every frame has to be whole
+    let pacing = &mut model.pacing;
+    pacing.measure(_update.since_last.as_secs_f32(), settings.frame_budget);
+    let triangles = model.figure.visible.triangles();
+    let progressive = match model.canvas {
+        _ if model.recording.is_some() => false,
+        // only back to drawing in one go well below capacity, so as not to
+        // keep switching at the edge of it
+        Some(_) => triangles > pacing.capacity() / 2,
+        None => triangles > pacing.capacity(),
+    };
+    let base = base_color(settings, model.clock);
+    if progressive {
+        let drawn = draw_progressively(app, settings, &model.figure, &model.sprites, &model.lines, pacing.capacity(), base, &mut model.canvas);
+        pacing.drew(drawn);
+    } else {
+        model.canvas = None;
+        pacing.drew(triangles);
+    }
+    if settings.trail.lingers() {
+        let (figure, sprites, lines, canvas) = (&model.figure, &model.sprites, &model.lines, model.canvas.as_ref());
+        draw_trail(app, settings, base, &mut model.accumulator, dt, |draw, encoder, pass| match canvas {
+            Some(canvas) => {
+                draw.texture(canvas.texture()).w_h(pass.size[0] as f32, pass.size[1] as f32);
+            }
+            None => draw_figure(draw, encoder, pass, settings, figure, sprites, lines),
+        });
+    } else {
+        model.accumulator = None;
+    }
+
+    if settings.hide_ui || model.recording.is_some() {
         return;
     }
 
@@ -264,7 +898,7 @@ fn update(app: &App, model: &mut Model, _update: Update) {
         ui.horizontal(|ui| {
             ui.label("Preset: ");
             ComboBox::from_label("")
-                .selected_text(format!("{}", &settings.default_preset))
+                .selected_text(settings.default_preset.clone())
                 .show_ui(ui, |ui| {
                     ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
                     for (key, _) in model.presets.iter() {
@@ -273,10 +907,31 @@ fn update(app: &App, model: &mut Model, _update: Update) {
                 });
             if ui.button("load preset").clicked() {
                 let preset = model.presets.get(&settings.default_preset).unwrap();
-                model.lsys_input = preset.lsystem.clone();
-                settings.angle = preset.angle;
-                settings.length = preset.length;
-                settings.level = preset.level;
+                apply_preset(preset, settings, &mut model.lsys_input, &mut model.timeline, &mut model.modulation);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("File: ");
+            ui.text_edit_singleline(&mut settings.file_path);
+            let path = Path::new(&settings.file_path);
+            if ui.button("save").clicked() {
+                let preset = current_preset(settings, &model.lsys_input, &model.timeline, &model.modulation);
+                match preset.save(path) {
+                    Ok(()) => println!("saved {}", path.display()),
+                    Err(err) => println!("{}", err),
+                }
+            }
+            if ui.button("load").clicked() {
+                match Preset::load(path) {
+                    Ok(preset) => {
+                        let name = path.file_stem().map_or(str!(path.display()), |stem| str!(stem.to_string_lossy()));
+                        apply_preset(&preset, settings, &mut model.lsys_input, &mut model.timeline, &mut model.modulation);
+                        model.presets.insert(name.clone(), preset);
+                        settings.default_preset = name;
+                    }
+                    Err(err) => println!("{}", err),
+                }
             }
         });
 
@@ -312,7 +967,7 @@ fn update(app: &App, model: &mut Model, _update: Update) {
 
         ui.horizontal(|ui| {
             ui.label("Start: ");
-            rule_edit_field(ui, &mut model.lsys_input.start, model.lsys_input.variables.clone());
+            rule_edit_field(ui, &mut model.lsys_input.start, &symbols);
         });
 
         ui.separator();
@@ -322,7 +977,7 @@ fn update(app: &App, model: &mut Model, _update: Update) {
                 ui.label("Variable");
                 for (idx, (key, _, _)) in model.lsys_input.rules.iter_mut().enumerate() {
                     ComboBox::from_id_source(idx)
-                    .selected_text(format!("{}", key))
+                    .selected_text(key.clone())
                     .show_ui(ui, |ui| {
                         ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
                         for var in model.lsys_input.variables.iter() {
@@ -334,7 +989,7 @@ fn update(app: &App, model: &mut Model, _update: Update) {
             ui.vertical(|ui| {
                 ui.label("Replacement Rule");
                 for (_, val, _) in model.lsys_input.rules.iter_mut() {
-                    rule_edit_field(ui, val, model.lsys_input.variables.clone());
+                    rule_edit_field(ui, val, &symbols);
                 }
             });
             ui.vertical(|ui| {
@@ -358,15 +1013,153 @@ fn update(app: &App, model: &mut Model, _update: Update) {
             settings.new_rule_buffer = ("".to_string(), "".to_string(), 1);
         }
 
+        ui.horizontal(|ui| {
+            ui.label("Palette: ");
+            let palette = &mut model.lsys_input.palette;
+            let mut removed = None;
+            for (idx, color) in palette.iter_mut().enumerate() {
+                ui.color_edit_button_rgb(color).context_menu(|ui| {
+                    if ui.button("remove").clicked() {
+                        removed = Some(idx);
+                        ui.close_menu();
+                    }
+                });
+            }
+            if let Some(idx) = removed {
+                palette.remove(idx);
+            }
+            if ui.button("+").clicked() {
+                palette.push([1.0, 1.0, 1.0]);
+            }
+        });
+
+        egui::CollapsingHeader::new("Surfaces").show(ui, |ui| {
+            let surfaces = &mut model.lsys_input.surfaces;
+            let mut removed = None;
+            for (idx, surface) in surfaces.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label("~");
+                    symbol_edit_field(ui, &mut surface.symbol);
+                    ComboBox::from_id_source(("surface kind", idx))
+                        .selected_text(surface.kind.name())
+                        .show_ui(ui, |ui| {
+                            ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+                            let kinds = [SurfaceKind::Circle, SurfaceKind::Polygon(6), SurfaceKind::Path(str!()), SurfaceKind::Sprite(str!())];
+                            for kind in kinds {
+                                let selected = surface.kind.name() == kind.name();
+                                if ui.selectable_label(selected, kind.name()).clicked() && !selected {
+                                    surface.kind = kind;
+                                }
+                            }
+                        });
+                    ui.add(egui::DragValue::new(&mut surface.size).speed(0.05).clamp_range(0.0..=100.0).prefix("size "));
+                    match &mut surface.kind {
+                        SurfaceKind::Circle => {}
+                        SurfaceKind::Polygon(sides) => {
+                            ui.add(egui::DragValue::new(sides).clamp_range(3..=64).suffix(" sides"));
+                        }
+                        SurfaceKind::Path(data) => {
+                            ui.add(egui::TextEdit::singleline(data).hint_text("svg path data"));
+                        }
+                        SurfaceKind::Sprite(image) => {
+                            ui.add(egui::TextEdit::singleline(image).hint_text("image file"));
+                        }
+                    }
+                    if ui.button("-").clicked() {
+                        removed = Some(idx);
+                    }
+                });
+            }
+            if let Some(idx) = removed {
+                surfaces.remove(idx);
+            }
+            if ui.button("+").clicked() {
+                surfaces.push(Surface { symbol: 'L', kind: SurfaceKind::Circle, size: 1.0 });
+            }
+        });
+
+        egui::CollapsingHeader::new("Commands").show(ui, |ui| {
+            let commands = &mut model.lsys_input.commands;
+            let mut removed = None;
+            egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                for (idx, (symbol, action)) in commands.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        symbol_edit_field(ui, symbol);
+                        ComboBox::from_id_source(("command", idx))
+                            .selected_text(action.name())
+                            .show_ui(ui, |ui| {
+                                ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+                                for a in Action::ALL {
+                                    ui.selectable_value(action, a, a.name());
+                                }
+                            });
+                        if ui.button("-").clicked() {
+                            removed = Some(idx);
+                        }
+                    });
+                }
+            });
+            if let Some(idx) = removed {
+                commands.remove(idx);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("+").clicked() {
+                    commands.push(('?', Action::NoOp));
+                }
+                if ui.button("reset").clicked() {
+                    *commands = default_commands();
+                }
+            });
+        });
+
+        egui::CollapsingHeader::new("Jitter").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("all: ");
+                jitter_edit(ui, "jitter", &mut model.lsys_input.jitter);
+            });
+            let symbol_jitter = &mut model.lsys_input.symbol_jitter;
+            let mut removed = None;
+            for (idx, (symbol, jitter)) in symbol_jitter.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    symbol_edit_field(ui, symbol);
+                    jitter_edit(ui, ("symbol jitter", idx), jitter);
+                    if ui.button("-").clicked() {
+                        removed = Some(idx);
+                    }
+                });
+            }
+            if let Some(idx) = removed {
+                symbol_jitter.remove(idx);
+            }
+            if ui.button("+").clicked() {
+                symbol_jitter.push(('+', model.lsys_input.jitter));
+            }
+        });
+
         ui.separator();
 
         ui.horizontal(|ui| {
             ui.label("n = ");
             ui.add(egui::Slider::new(&mut settings.level, 0..=20));
+            ui.checkbox(&mut settings.morph, "morph?");
+        });
+        ui.add_enabled_ui(settings.morph, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("morph speed: ");
+                ui.add(egui::Slider::new(&mut settings.morph_speed, 0.1..=5.0).suffix(" n/s"));
+            });
         });
         ui.horizontal(|ui| {
             ui.label("length: ");
-            ui.add(egui::Slider::new(&mut settings.length, 0..=100));
+            ui.add(egui::Slider::new(&mut settings.length, 0.0..=100.0));
+            ComboBox::from_id_source("normalize")
+                .selected_text(format!("normalize: {}", settings.normalize.name()))
+                .show_ui(ui, |ui| {
+                    ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+                    for mode in Normalize::ALL {
+                        ui.selectable_value(&mut settings.normalize, mode, mode.name());
+                    }
+                });
         });
         ui.horizontal(|ui| {
             ui.label("angle: ");
@@ -376,13 +1169,65 @@ fn update(app: &App, model: &mut Model, _update: Update) {
                     format!("{:>3.0}", n)
                 })
             );
-            ui.checkbox(&mut settings.animate_angle, "animate?");
         });
-        
-        ui.add_enabled_ui(settings.animate_angle, |ui| {
+
+        ui.horizontal(|ui| {
+            let lsys_input = &mut model.lsys_input;
+            ui.label("turtle: ");
+            ui.add(egui::DragValue::new(&mut lsys_input.origin.x).prefix("x "));
+            ui.add(egui::DragValue::new(&mut lsys_input.origin.y).prefix("y "));
+            ui.add(egui::DragValue::new(&mut lsys_input.origin.z).prefix("z "));
+            ui.add(egui::DragValue::new(&mut lsys_input.heading).clamp_range(-360.0..=360.0).prefix("heading ").suffix("°"));
+            ui.add(egui::DragValue::new(&mut lsys_input.step).speed(0.01).clamp_range(0.0..=10.0).prefix("step ×"));
+        });
+        ui.horizontal(|ui| {
+            let lsys_input = &mut model.lsys_input;
+            ui.label("scaling: ");
+            ui.add(egui::DragValue::new(&mut lsys_input.length_factor).speed(0.01).clamp_range(0.0..=10.0).prefix("\" step ×"));
+            ui.add(egui::DragValue::new(&mut lsys_input.angle_factor).speed(0.01).clamp_range(0.0..=10.0).prefix("; angle ×"));
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("tropism: ");
+            ui.add(egui::DragValue::new(&mut settings.tropism.x).speed(0.01).prefix("x "));
+            ui.add(egui::DragValue::new(&mut settings.tropism.y).speed(0.01).prefix("y "));
+            ui.add(egui::DragValue::new(&mut settings.tropism.z).speed(0.01).prefix("z "));
+            ui.add(egui::Slider::new(&mut settings.susceptibility, -1.0..=1.0).text("susceptibility"));
+        });
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut settings.grow, "grow?");
+            ui.add_enabled_ui(settings.grow, |ui| {
+                ComboBox::from_id_source("growth order")
+                    .selected_text(settings.grow_order.label())
+                    .show_ui(ui, |ui| {
+                        ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+                        for order in GrowthOrder::ALL {
+                            ui.selectable_value(&mut settings.grow_order, order, order.label());
+                        }
+                    });
+                let play_label = if settings.grow_playing { "pause" } else { "play" };
+                if ui.button(play_label).clicked() {
+                    if !settings.grow_playing && settings.grow_progress >= 1.0 {
+                        settings.grow_progress = 0.0;
+                    }
+                    settings.grow_playing = !settings.grow_playing;
+                }
+            });
+        });
+
+        ui.add_enabled_ui(settings.grow, |ui| {
             ui.horizontal(|ui| {
-                ui.label("animation speed: ");
-                ui.add(egui::Slider::new(&mut settings.speed, 0.0..=10.0));
+                ui.label("growth: ");
+                if ui.add(egui::Slider::new(&mut settings.grow_progress, 0.0..=1.0)).dragged() {
+                    settings.grow_playing = false;
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("growth speed: ");
+                ui.add(egui::Slider::new(&mut settings.grow_speed, 0.0..=10.0));
             });
         });
 
@@ -399,19 +1244,347 @@ fn update(app: &App, model: &mut Model, _update: Update) {
         });
 
         ui.horizontal(|ui| {
-            if ui.button(format!("Reset Scale ({:.1})", settings.scale)).clicked() {
+            if ui.button(format!("Reset Scale ({:.3})", settings.scale)).clicked() {
                 settings.scale = 1.0;
+                settings.offset = DVec2::ZERO;
             }
             if ui.button("Recenter").clicked() {
-                settings.offset = pt2(0.0, 0.0);
+                settings.offset = DVec2::ZERO;
+            }
+            if ui.button("Fit").clicked() {
+                if let Some((_, shapes, ..)) = &model.figure.shapes {
+                    fit_to_view(settings, shapes, app.window_rect());
+                }
+            }
+            let mut budget = settings.frame_budget * 1000.0;
+            if ui.add(egui::DragValue::new(&mut budget).speed(1.0).clamp_range(5.0..=1000.0).prefix("frame budget ").suffix(" ms")).changed() {
+                settings.frame_budget = budget / 1000.0;
+            }
+            ui.checkbox(&mut settings.hold_drawing, "hold drawing");
+            if let Some(canvas) = &model.canvas {
+                ui.label(format!("drawn {:.0}%", canvas.progress() * 100.0));
+            }
+            if ui.button("export svg").clicked() {
+                export_svg(app, settings, &model.lsys_input, model.clock);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let camera = &mut settings.camera;
+            ui.label("camera: ");
+            ui.drag_angle(&mut camera.yaw);
+            ui.drag_angle(&mut camera.pitch);
+            ui.add(egui::DragValue::new(&mut camera.distance).speed(10.0).clamp_range(100.0..=10000.0).prefix("distance "));
+            if ui.button("Reset Camera").clicked() {
+                *camera = Camera::default();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("colour: ");
+            ui.checkbox(&mut settings.cycle_color, "cycle?");
+            ui.add_enabled_ui(!settings.cycle_color, |ui| {
+                ui.add(egui::Slider::new(&mut settings.hue, 0.0..=1.0).text("h"));
+                ui.add(egui::Slider::new(&mut settings.saturation, 0.0..=1.0).text("s"));
+                ui.add(egui::Slider::new(&mut settings.brightness, 0.0..=1.0).text("v"));
+            });
+        });
+
+        ui.horizontal(|ui| {
+            let coloring = &mut settings.coloring;
+            ui.label("colour by: ");
+            ComboBox::from_id_source("color mode")
+                .selected_text(coloring.mode.name())
+                .show_ui(ui, |ui| {
+                    ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+                    for mode in ColorMode::ALL {
+                        ui.selectable_value(&mut coloring.mode, mode, mode.name());
+                    }
+                });
+            if let Some(gradient) = coloring.gradients.get_mut(&coloring.mode) {
+                let mut removed = None;
+                for (idx, stop) in gradient.stops.iter_mut().enumerate() {
+                    ui.color_edit_button_rgb(stop).context_menu(|ui| {
+                        if ui.button("remove").clicked() {
+                            removed = Some(idx);
+                            ui.close_menu();
+                        }
+                    });
+                }
+                if let Some(idx) = removed {
+                    gradient.stops.remove(idx);
+                }
+                if ui.button("+").clicked() {
+                    gradient.stops.push([1.0, 1.0, 1.0]);
+                }
+                ui.checkbox(&mut coloring.animate, "animate?");
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let taper = &mut settings.taper;
+            ui.label("taper by: ");
+            ComboBox::from_id_source("taper mode")
+                .selected_text(taper.mode.name())
+                .show_ui(ui, |ui| {
+                    ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+                    for mode in TaperMode::ALL {
+                        ui.selectable_value(&mut taper.mode, mode, mode.name());
+                    }
+                });
+            ui.add_enabled_ui(taper.mode != TaperMode::None, |ui| {
+                ui.add(egui::DragValue::new(&mut taper.base).speed(0.1).clamp_range(0.0..=100.0).prefix("base "));
+                ui.add(egui::DragValue::new(&mut taper.ratio).speed(0.01).clamp_range(0.0..=1.0).prefix("ratio "));
+                ui.add(egui::DragValue::new(&mut taper.min).speed(0.05).clamp_range(0.0..=100.0).prefix("min "));
+            });
+        });
+        ui.horizontal(|ui| {
+            let smoothing = &mut settings.smoothing;
+            ui.label("smoothing: ");
+            ComboBox::from_id_source("smoothing mode")
+                .selected_text(smoothing.mode.name())
+                .show_ui(ui, |ui| {
+                    ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+                    for mode in SmoothMode::ALL {
+                        ui.selectable_value(&mut smoothing.mode, mode, mode.name());
+                    }
+                });
+            ui.add_enabled_ui(smoothing.mode == SmoothMode::Round, |ui| {
+                ui.add(egui::DragValue::new(&mut smoothing.radius).speed(0.1).clamp_range(0.0..=1000.0).prefix("radius "));
+            });
+        });
+        ui.horizontal(|ui| {
+            let symmetry = &mut settings.symmetry;
+            ui.label("symmetry: ");
+            ui.add(egui::DragValue::new(&mut symmetry.copies).clamp_range(1..=64).suffix(" copies"));
+            ui.checkbox(&mut symmetry.mirror, "mirror?");
+            ui.add_enabled_ui(symmetry.mirror, |ui| {
+                ui.add(egui::DragValue::new(&mut symmetry.axis).speed(1.0).clamp_range(0.0..=180.0).prefix("axis ").suffix("°"));
+            });
+            ui.add_enabled_ui(!symmetry.is_none(), |ui| {
+                ui.add(egui::DragValue::new(&mut symmetry.hue_shift).speed(0.005).clamp_range(-1.0..=1.0).prefix("hue shift "));
+            });
+        });
+        ui.horizontal(|ui| {
+            let trail = &mut settings.trail;
+            ui.label("trail: ");
+            ui.add(egui::DragValue::new(&mut trail.fade).speed(0.005).clamp_range(0.0..=1.0).prefix("fade ").suffix("/s"));
+            ui.color_edit_button_rgb(&mut trail.background);
+            ComboBox::from_id_source("blend mode")
+                .selected_text(trail.blend.name())
+                .show_ui(ui, |ui| {
+                    ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+                    for mode in BlendMode::ALL {
+                        ui.selectable_value(&mut trail.blend, mode, mode.name());
+                    }
+                });
+        });
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("mesh: ");
+            ui.add(egui::DragValue::new(&mut settings.mesh_radius).speed(0.05).clamp_range(0.01..=100.0).prefix("radius "));
+            ui.add(egui::DragValue::new(&mut settings.mesh_sides).clamp_range(3..=64).prefix("sides "));
+            if ui.button("export obj").clicked() {
+                export_mesh(app, settings, &model.lsys_input, model.clock, "obj");
+            }
+            if ui.button("export glb").clicked() {
+                export_mesh(app, settings, &model.lsys_input, model.clock, "glb");
+            }
+        });
+
+        ui.separator();
+
+        egui::CollapsingHeader::new("Timeline").show(ui, |ui| {
+            let timeline = &mut model.timeline;
+            ui.horizontal(|ui| {
+                let play_label = if timeline.playing { "pause" } else { "play" };
+                if ui.button(play_label).clicked() {
+                    if !timeline.playing && timeline.time >= timeline.duration {
+                        timeline.time = 0.0;
+                    }
+                    timeline.playing = !timeline.playing;
+                }
+                let scrub = ui.add(egui::Slider::new(&mut timeline.time, 0.0..=timeline.duration).suffix("s"));
+                if scrub.changed() {
+                    for (param, value) in timeline.sample() {
+                        set_param(settings, &mut model.lsys_input, param, value);
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("duration: ");
+                ui.add(egui::DragValue::new(&mut timeline.duration).clamp_range(0.1..=600.0).suffix("s"));
+                ui.checkbox(&mut timeline.looping, "loop?");
+            });
+
+            for track in timeline.tracks.iter_mut() {
+                ui.horizontal_wrapped(|ui| {
+                    ui.label(format!("{}: ", track.param.name()));
+                    for idx in 0..track.keys().len() {
+                        let key = track.keys()[idx];
+                        let label = format!("{:.2}s = {:.2} ({}) ×", key.time, key.value, key.easing.name());
+                        if ui.button(label).clicked() {
+                            track.remove(idx);
+                            break;
+                        }
+                    }
+                });
+            }
+            timeline.tracks.retain(|track| !track.keys().is_empty());
+
+            ui.horizontal(|ui| {
+                let (param, easing) = &mut settings.new_key_buffer;
+                ComboBox::from_id_source("key param")
+                    .selected_text(param.name())
+                    .show_ui(ui, |ui| {
+                        ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+                        let weights = (0..model.lsys_input.rules.len()).map(Param::RuleWeight);
+                        for p in Param::FIXED.into_iter().chain(weights) {
+                            ui.selectable_value(param, p, p.name());
+                        }
+                    });
+                ComboBox::from_id_source("key easing")
+                    .selected_text(easing.name())
+                    .show_ui(ui, |ui| {
+                        ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+                        for e in Easing::ALL {
+                            ui.selectable_value(easing, e, e.name());
+                        }
+                    });
+                if ui.button("add key").clicked() {
+                    let (param, easing) = settings.new_key_buffer;
+                    let key = Keyframe {
+                        time: timeline.time,
+                        value: param_value(settings, &model.lsys_input, param),
+                        easing,
+                    };
+                    timeline.track_mut(param).insert(key);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui.button("record").clicked() {
+                    let timestamp = std::time::UNIX_EPOCH.elapsed().unwrap().as_secs();
+                    model.recording = Some(Recording {
+                        name: format!("{}-{}", app.exe_name().unwrap(), timestamp),
+                        frame: 0,
+                    });
+                    model.clock = 0.0;
+                    timeline.time = 0.0;
+                    timeline.playing = true;
+                }
+                ui.add(egui::DragValue::new(&mut settings.record_fps).clamp_range(1.0..=120.0).suffix(" fps"));
+            });
+        });
+
+        egui::CollapsingHeader::new("Modulation").show(ui, |ui| {
+            let modulation = &mut model.modulation;
+            let mut removed = None;
+            for (idx, source) in modulation.sources.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}: {}", idx, source.name()));
+                    match source {
+                        Source::Lfo { waveform, rate, phase } => {
+                            ComboBox::from_id_source(("waveform", idx))
+                                .selected_text(waveform.name())
+                                .show_ui(ui, |ui| {
+                                    ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+                                    for w in Waveform::ALL {
+                                        ui.selectable_value(waveform, w, w.name());
+                                    }
+                                });
+                            ui.add(egui::DragValue::new(rate).speed(0.01).clamp_range(0.0..=20.0).suffix(" Hz"));
+                            ui.add(egui::DragValue::new(phase).speed(0.01).prefix("phase "));
+                        }
+                        Source::Noise { rate, seed } => {
+                            ui.add(egui::DragValue::new(rate).speed(0.01).clamp_range(0.0..=20.0).suffix(" Hz"));
+                            ui.label("seed: ");
+                            integer_edit_field(ui, seed, 80.0);
+                        }
+                        Source::Envelope { attack, hold, release, looping, .. } => {
+                            ui.add(egui::DragValue::new(attack).speed(0.05).clamp_range(0.0..=60.0).prefix("attack ").suffix("s"));
+                            ui.add(egui::DragValue::new(hold).speed(0.05).clamp_range(0.0..=60.0).prefix("hold ").suffix("s"));
+                            ui.add(egui::DragValue::new(release).speed(0.05).clamp_range(0.0..=60.0).prefix("release ").suffix("s"));
+                            ui.checkbox(looping, "loop?");
+                        }
+                    }
+                    if ui.button("-").clicked() {
+                        removed = Some(idx);
+                    }
+                });
+            }
+            if let Some(idx) = removed {
+                modulation.remove_source(idx);
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("+ lfo").clicked() {
+                    modulation.sources.push(Source::Lfo { waveform: Waveform::Sine, rate: 0.2, phase: 0.0 });
+                }
+                if ui.button("+ noise").clicked() {
+                    modulation.sources.push(Source::Noise { rate: 1.0, seed: random() });
+                }
+                if ui.button("+ envelope").clicked() {
+                    modulation.sources.push(Source::Envelope { attack: 1.0, hold: 1.0, release: 1.0, looping: false, start: model.clock });
+                }
+                if ui.button("trigger envelopes").clicked() {
+                    modulation.trigger(model.clock);
+                }
+            });
+
+            ui.separator();
+
+            let mut removed = None;
+            for (idx, route) in modulation.routes.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut route.enabled, "");
+                    ComboBox::from_id_source(("route source", idx))
+                        .selected_text(format!("{}", route.source))
+                        .width(40.0)
+                        .show_ui(ui, |ui| {
+                            ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+                            for (i, source) in modulation.sources.iter().enumerate() {
+                                ui.selectable_value(&mut route.source, i, format!("{}: {}", i, source.name()));
+                            }
+                        });
+                    ui.label("→");
+                    ComboBox::from_id_source(("route target", idx))
+                        .selected_text(route.target.name())
+                        .show_ui(ui, |ui| {
+                            ui.visuals_mut().selection.bg_fill = Color32::from_rgb(60, 5, 20);
+                            let weights = (0..model.lsys_input.rules.len()).map(Param::RuleWeight);
+                            for p in Param::FIXED.into_iter().chain(weights) {
+                                ui.selectable_value(&mut route.target, p, p.name());
+                            }
+                        });
+                    ui.add(egui::DragValue::new(&mut route.depth).speed(0.1).prefix("depth "));
+                    ui.add(egui::DragValue::new(&mut route.offset).speed(0.1).prefix("offset "));
+                    if ui.button("-").clicked() {
+                        removed = Some(idx);
+                    }
+                });
+            }
+            if let Some(idx) = removed {
+                modulation.routes.remove(idx);
+            }
+
+            if ui.button("+ route").clicked() && !modulation.sources.is_empty() {
+                modulation.routes.push(Route {
+                    source: 0,
+                    target: Param::Angle,
+                    depth: 10.0,
+                    offset: settings.angle,
+                    enabled: true,
+                });
             }
-            ui.checkbox(&mut settings.clear_bg, "clear bg?");
         });
 
         ui.separator();
 
         ui.horizontal(|ui| {
-            ui.label(format!("seed: "));
+            ui.label("seed: ");
             integer_edit_field(ui, &mut settings.seed, 200.0);
             if ui.button("randomize").clicked() {
                 settings.seed = random();
@@ -431,35 +1604,99 @@ fn key_pressed(app: &App, model: &mut Model, key: Key) {
     }
 }
 
+/// How much one line of mouse wheel scrolling, or one press of `+` or `-`,
+/// zooms by.
+const ZOOM_PER_LINE: f64 = 1.1;
+/// Pixels of trackpad scrolling that zoom as much as one line.
+const PIXELS_PER_LINE: f64 = 50.0;
+/// How far an arrow key pans, in pixels.
+const PAN_STEP: f64 = 50.0;
+/// How much of the window a fitted figure fills.
+const FIT_MARGIN: f32 = 0.9;
+
+/// Zooms by `factor`, keeping the point under `anchor` where it is.
+fn zoom(settings: &mut Settings, factor: f64, anchor: DVec2) {
+    let scale = settings.scale * factor;
+    if !scale.is_normal() {
+        return;
+    }
+    settings.offset = anchor + (settings.offset - anchor) * factor;
+    settings.scale = scale;
+}
+
+/// Centres `shapes` in the window and zooms them to fill most of it.
+fn fit_to_view(settings: &mut Settings, shapes: &[Shape], window: Rect) {
+    let (sin, cos) = settings.rotation.to_radians().sin_cos();
+    let bounds = Bounds::around(scene::extent(shapes).into_iter().map(|p| {
+        let p = p.as_f32();
+        pt2(p.x * cos - p.y * sin, p.x * sin + p.y * cos)
+    }));
+    let size = bounds.size();
+    if size.max_element() <= 0.0 {
+        return;
+    }
+    settings.scale = (FIT_MARGIN * (window.w() / size.x).min(window.h() / size.y)) as f64;
+    settings.offset = window.xy().as_f64() - bounds.centre().as_f64() * settings.scale;
+}
+
 fn event(app: &App, model: &mut Model, event: WindowEvent) {
     match event {
         MouseWheel(amount, _phase) => {
-            match amount {
-                MouseScrollDelta::PixelDelta(pos) => {
-                    model.settings.scale += pos.y as f32 / 50.0;
-                    model.settings.scale = model.settings.scale.clamp(0.2, 10.0);
+            if model.egui.ctx().is_pointer_over_area() {
+                return;
+            }
+            let lines = match amount {
+                MouseScrollDelta::LineDelta(_, y) => y as f64,
+                MouseScrollDelta::PixelDelta(pos) => pos.y / PIXELS_PER_LINE,
+            };
+            zoom(&mut model.settings, ZOOM_PER_LINE.powf(lines), DVec2::new(app.mouse.x as f64, app.mouse.y as f64));
+        }
+        KeyPressed(key) => {
+            if model.egui.ctx().wants_keyboard_input() {
+                return;
+            }
+            let settings = &mut model.settings;
+            match key {
+                Key::Left => settings.offset.x += PAN_STEP,
+                Key::Right => settings.offset.x -= PAN_STEP,
+                Key::Up => settings.offset.y -= PAN_STEP,
+                Key::Down => settings.offset.y += PAN_STEP,
+                Key::Equals | Key::Plus | Key::NumpadAdd => zoom(settings, ZOOM_PER_LINE, DVec2::ZERO),
+                Key::Minus | Key::NumpadSubtract => zoom(settings, 1.0 / ZOOM_PER_LINE, DVec2::ZERO),
+                Key::Key0 => {
+                    settings.scale = 1.0;
+                    settings.offset = DVec2::ZERO;
                 }
-                _ => {},
+                Key::F => if let Some((_, shapes, ..)) = &model.figure.shapes {
+                    fit_to_view(settings, shapes, app.window_rect());
+                },
+                _ => {}
             }
         }
         MousePressed(_button) => {
             match _button {
-                MouseButton::Left => {
-                    if !model.egui.ctx().is_pointer_over_area() {
-                        model.drag_event.is_dragging = true;
-                        model.drag_event.start_pos = pt2(app.mouse.x, app.mouse.y);
-                    }
+                MouseButton::Left if !model.egui.ctx().is_pointer_over_area() => {
+                    model.drag_event.is_dragging = true;
+                    model.drag_event.start_pos = pt2(app.mouse.x, app.mouse.y);
+                }
+                MouseButton::Right if !model.egui.ctx().is_pointer_over_area() => {
+                    model.drag_event.is_orbiting = true;
+                    model.drag_event.start_pos = pt2(app.mouse.x, app.mouse.y);
                 }
                 _ => {}
             }
         }
         MouseReleased(_button) => {
             model.drag_event.is_dragging = false;
+            model.drag_event.is_orbiting = false;
             model.drag_event.start_pos = pt2(0.0, 0.0);
         }
         MouseMoved(_pos) => {
             if model.drag_event.is_dragging {
-                model.settings.offset += _pos - model.drag_event.start_pos;
+                model.settings.offset += (_pos - model.drag_event.start_pos).as_f64();
+                model.drag_event.start_pos = _pos;
+            } else if model.drag_event.is_orbiting {
+                model.settings.camera.orbit(_pos - model.drag_event.start_pos);
                 model.drag_event.start_pos = _pos;
             }
         }
@@ -467,41 +1704,187 @@ fn event(app: &App, model: &mut Model, event: WindowEvent) {
     }
 }
 
-fn view(app: &App, model: &Model, frame: Frame){
-    if model.settings.clear_bg {
-        frame.clear(BLACK);
+/// The point of the figure at the window's centre, undoing the offset,
+/// rotation and zoom.
+fn view_centre(settings: &Settings) -> DVec2 {
+    to_figure(DVec2::ZERO, settings)
+}
+
+/// Undoes the offset, rotation and zoom of a point in the window.
+fn to_figure(p: DVec2, settings: &Settings) -> DVec2 {
+    let (sin, cos) = (-settings.rotation.to_radians() as f64).sin_cos();
+    let p = p - settings.offset;
+    DVec2::new(p.x * cos - p.y * sin, p.x * sin + p.y * cos) / settings.scale
+}
+
+/// The window's bounds in the figure's units.
+fn figure_bounds(window: Rect, settings: &Settings) -> Bounds {
+    Bounds::around([
+        pt2(window.left(), window.bottom()),
+        pt2(window.right(), window.bottom()),
+        pt2(window.right(), window.top()),
+        pt2(window.left(), window.top()),
+    ].map(|corner| to_figure(corner.as_f64(), settings).as_f32()))
+}
+
+/// Draws the polygons and sprites among `shapes`, which go over the lines,
+/// zoomed by `scale` about `centre` like the lines are.
+fn draw_fills(draw: &Draw, shapes: &[Shape], sprites: &HashMap<String, Option<wgpu::Texture>>, base: [f32; 3], centre: DVec2, scale: f64) {
+    let place = |p: DVec2| ((p - centre) * scale).as_f32();
+    for shape in shapes.iter() {
+        match shape {
+            Shape::Line { .. } | Shape::Curve { .. } => {}
+            Shape::Fill { points, color } => {
+                let [r, g, b] = color.resolve(base);
+                draw.polygon().rgb(r, g, b).points(points.iter().map(|&p| place(p)));
+            }
+            Shape::Sprite { path, position, heading } => {
+                if let Some(Some(texture)) = sprites.get(path) {
+                    let [w, h] = texture.size();
+                    let width = heading.length() * scale as f32;
+                    draw.texture(texture)
+                    .xy(place(*position))
+                    .w_h(width, width * h as f32 / w as f32)
+                    .rotate(heading.y.atan2(heading.x));
+                }
+            }
+        }
     }
+}
 
-    let draw = app.draw();
-    let mut rules: HashMap<String, Vec<(String, u64)>> = HashMap::new();
+/// Where the figure's lines go in the window, relative to its centre.
+fn line_transform(settings: &Settings) -> Transform {
+    Transform {
+        centre: view_centre(settings),
+        rotation: settings.rotation.to_radians(),
+        scale: settings.scale,
+    }
+}
 
-    model.lsys_input.rules.iter().for_each(|(k, v, weight)| {
-        let r = rules.entry(k.to_string());
-        r.or_default().push((v.to_string(), *weight));
-    });
+/// Draws the next `budget` triangles of the figure into the canvas,
+/// starting over whenever the view or the figure changes, though with
+/// drawings held only once the last one is done. Returns how many triangles were drawn.
+#[allow(clippy::too_many_arguments)]
+fn draw_progressively(
+    app: &App,
+    settings: &Settings,
+    figure: &Figure,
+    sprites: &HashMap<String, Option<wgpu::Texture>>,
+    renderer: &LineRenderer,
+    budget: usize,
+    base: [f32; 3],
+    canvas: &mut Option<Canvas>,
+) -> usize {
+    let Some((_, shapes, _, lines)) = &figure.shapes else { return 0 };
+    let window = app.main_window();
+    let rect = window.rect();
+    let size = [rect.w() as u32, rect.h() as u32];
+    // a minimised window has nothing to draw into
+    if size.contains(&0) {
+        *canvas = None;
+        return 0;
+    }
+    if canvas.as_ref().is_none_or(|canvas| canvas.size() != size) {
+        *canvas = Some(Canvas::new(window.device(), size));
+    }
+    let Some(canvas) = canvas else { return 0 };
 
-    let mut lsystem = LSystem::new(
-        &model.lsys_input.start,
-        rules,
-        model.settings.length,
-        model.settings.angle,
-        model.settings.seed,
-    );
+    let view = View {
+        figure: figure.version,
+        offset: settings.offset,
+        rotation: settings.rotation.to_radians(),
+        scale: settings.scale,
+        blank: settings.trail.blend.blank(),
+    };
+    if canvas.stale(view, settings.hold_drawing) {
+        canvas.restart(view, lines.clone(), figure.visible.clone(), line_transform(settings), base, settings.hold_drawing);
+    }
+    canvas.step(window.device(), window.queue(), renderer, budget, |draw, base| {
+        draw_fills(draw, shapes, sprites, base, view_centre(settings), settings.scale);
+    })
+}
+
+/// Fades the accumulator by a frame lasting `dt` seconds and has `figure`
+/// draw over it.
+fn draw_trail(
+    app: &App,
+    settings: &Settings,
+    base: [f32; 3],
+    accumulator: &mut Option<Accumulator>,
+    dt: f32,
+    figure: impl FnOnce(&Draw, &mut wgpu::CommandEncoder, &Pass),
+) {
+    let window = app.main_window();
+    let rect = window.rect();
+    let size = [rect.w() as u32, rect.h() as u32];
+    if size.contains(&0) {
+        *accumulator = None;
+        return;
+    }
+    if accumulator.as_ref().is_none_or(|accumulator| accumulator.size() != size) {
+        *accumulator = Some(Accumulator::new(window.device(), size));
+    }
+    if let Some(accumulator) = accumulator {
+        accumulator.step(window.device(), window.queue(), &settings.trail, base, dt, figure);
+    }
+}
+
+/// Draws the figure's lines in `pass` and everything else into `draw`, which
+/// goes over them.
+fn draw_figure(
+    draw: &Draw,
+    encoder: &mut wgpu::CommandEncoder,
+    pass: &Pass,
+    settings: &Settings,
+    figure: &Figure,
+    sprites: &HashMap<String, Option<wgpu::Texture>>,
+    renderer: &LineRenderer,
+) {
+    let Some((_, shapes, _, lines)) = &figure.shapes else { return };
+    // everything is drawn relative to the window's centre, so that deep zooms
+    // don't lose precision
+    let (centre, scale) = (view_centre(settings), settings.scale);
+    renderer.draw(encoder, pass, lines, &figure.visible.ranges, line_transform(settings));
+
+    draw_fills(&draw.rotate(settings.rotation.to_radians()), shapes, sprites, pass.base, centre, scale);
+}
+
+/// Points sampled along each curve piece when drawing smoothed lines.
+const CURVE_STEPS: usize = 8;
+
+fn view(app: &App, model: &Model, frame: Frame){
+    let settings = &model.settings;
+    let draw = app.draw();
+    let [r, g, b] = settings.trail.background;
+    frame.clear(rgb(r, g, b));
+    let window = app.window_rect();
+    match (&model.accumulator, &model.canvas) {
+        (Some(accumulator), _) => {
+            draw.texture(&accumulator.texture).wh(window.wh());
+        }
+        (None, Some(canvas)) => {
+            draw.color_blend(settings.trail.blend.component()).texture(canvas.texture()).wh(window.wh());
+        }
+        (None, None) => {
+            let main_window = app.main_window();
+            let pass = Pass {
+                device: main_window.device(),
+                target: frame.texture_view(),
+                size: [window.w() as u32, window.h() as u32],
+                samples: frame.texture_msaa_samples(),
+                blend: settings.trail.blend,
+                clear: None,
+                base: base_color(settings, model.clock),
+            };
+            let draw = draw.color_blend(settings.trail.blend.component());
+            draw_figure(&draw, &mut frame.command_encoder(), &pass, settings, &model.figure, &model.sprites, &model.lines);
+        }
+    }
 
-    for section_points in lsystem.draw(model.settings.level, model.settings.scale) {
-        draw.polyline()
-        .weight(1.0)
-        .hsv(
-            map_range((app.time / 2.0).sin(), -1.0, 1.0, 0.0, 1.0),
-            map_range((app.time / 3.0).cos(), -1.0, 1.0, 0.5, 1.0), 
-            map_range((app.time * 10.0).sin(), -1.0, 1.0, 0.8, 1.0))
-        .points(section_points)
-        .xy(model.settings.offset)
-        .rotate(model.settings.rotation.to_radians());
-    }
-    
     draw.to_frame(app, &frame).unwrap();
-    model.egui.draw_to_frame(&frame).unwrap();
+    if model.recording.is_none() {
+        model.egui.draw_to_frame(&frame).unwrap();
+    }
 }
 
 fn raw_window_event(_app: &App, model: &mut Model, event: &nannou::winit::event::WindowEvent) {
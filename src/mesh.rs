@@ -0,0 +1,484 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::f32::consts::TAU;
+
+use nannou::glam::{DVec3, Vec3};
+
+use crate::lsystem::Section;
+
+/// A point a tube passes through, with its radius and colour there.
+type Point = (Vec3, f32, [f32; 3]);
+
+#[derive(Default)]
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    /// Rgb in 0 to 1, one per vertex.
+    pub colors: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+/// Tubes leaving a joint with directions closer than this cosine can't both
+/// be joined there.
+const PARALLEL: f32 = 0.999;
+
+/// A run of a section between the points where other sections meet it.
+struct Piece {
+    points: Vec<Point>,
+    /// The joints at its start and end, if other pieces meet it there.
+    ends: [Option<usize>; 2],
+}
+
+impl Piece {
+    /// The way the piece leaves its start, or its end if `side` is 1.
+    fn direction(&self, side: usize) -> Vec3 {
+        let points = &self.points;
+        match side {
+            0 => (points[1].0 - points[0].0).normalize(),
+            _ => (points[points.len() - 2].0 - points[points.len() - 1].0).normalize(),
+        }
+    }
+}
+
+impl Mesh {
+    /// Sweeps a circle of `sides` vertices along every section, with a radius
+    /// of `radius` times the stroke width at each point. `colors` holds the
+    /// colour of each section's points and `widths` the width of its
+    /// segments, each point taking the width of the segment that ends there.
+    ///
+    /// Where sections meet, such as where a branch leaves its parent, their
+    /// tubes stop a little short of the point and are joined by the convex
+    /// hull around their end rings, so that a connected figure becomes one
+    /// closed surface with every vertex shared. Tubes that meet at too
+    /// shallow an angle, or are too short for their thickness, to be joined
+    /// that way are closed off on their own and overlap instead, as do
+    /// sections that cross without meeting at a point.
+    pub fn tubes(sections: &[Section], colors: &[Vec<[f32; 3]>], widths: &[Vec<f32>], radius: f32, sides: usize) -> Mesh {
+        let sides = sides.max(3);
+        let mut paths = Vec::with_capacity(sections.len());
+        for ((section, colors), widths) in sections.iter().zip(colors.iter()).zip(widths.iter()) {
+            if widths.is_empty() {
+                continue;
+            }
+            let point_widths = std::iter::once(&widths[0]).chain(widths.iter());
+            // drop repeated points, which have no direction to sweep along
+            let mut points: Vec<Point> = Vec::with_capacity(section.points.len());
+            for ((p, &w), &color) in section.points.iter().zip(point_widths).zip(colors.iter()) {
+                let p = p.as_f32();
+                if points.last().is_none_or(|&(last, _, _)| last.distance_squared(p) > f32::EPSILON) {
+                    points.push((p, w * radius, color));
+                }
+            }
+            if points.len() >= 2 {
+                paths.push(points);
+            }
+        }
+
+        let mut pieces = Mesh::pieces(&paths);
+        let mut joints: BTreeMap<usize, Vec<(usize, usize)>> = BTreeMap::new();
+        for (idx, piece) in pieces.iter().enumerate() {
+            for (side, node) in piece.ends.iter().enumerate() {
+                if let Some(node) = node {
+                    joints.entry(*node).or_default().push((idx, side));
+                }
+            }
+        }
+        // a tube leaving along another one overlaps it whatever is done, so
+        // only the first is joined
+        for ends in joints.values_mut() {
+            let mut kept: Vec<(usize, usize)> = Vec::with_capacity(ends.len());
+            for &(idx, side) in ends.iter() {
+                let dir = pieces[idx].direction(side);
+                if kept.iter().all(|&(other, other_side)| pieces[other].direction(other_side).dot(dir) <= PARALLEL) {
+                    kept.push((idx, side));
+                } else {
+                    pieces[idx].ends[side] = None;
+                }
+            }
+            *ends = kept;
+        }
+        for ends in joints.values().filter(|ends| ends.len() < 2) {
+            ends.iter().for_each(|&(idx, side)| pieces[idx].ends[side] = None);
+        }
+        joints.retain(|_, ends| ends.len() >= 2);
+
+        // Joints that can't be built are given up on and everything is swept
+        // again, since pulling their tubes back moved the rings at the tubes'
+        // other ends.
+        let mut failed = HashSet::new();
+        loop {
+            let joined = |node: &Option<usize>| node.is_some_and(|node| !failed.contains(&node));
+            let mut pulls = vec![[0.0; 2]; pieces.len()];
+            let mut infeasible = Vec::new();
+            for (&node, ends) in joints.iter().filter(|(node, _)| !failed.contains(*node)) {
+                match pull_back(&pieces, ends, joined) {
+                    Some(pull) => ends.iter().for_each(|&(idx, side)| pulls[idx][side] = pull),
+                    None => infeasible.push(node),
+                }
+            }
+            failed.extend(infeasible);
+            let active: Vec<(usize, &Vec<(usize, usize)>)> = joints.iter()
+                .filter(|(node, _)| !failed.contains(*node))
+                .map(|(&node, ends)| (node, ends))
+                .collect();
+
+            let mut mesh = Mesh::default();
+            let mut swept = Vec::with_capacity(pieces.len());
+            for (piece, [start, end]) in pieces.iter().zip(pulls) {
+                let mut points = piece.points.clone();
+                trim(&mut points, start, false);
+                trim(&mut points, end, true);
+                let rings = mesh.tube(&points, sides);
+                swept.push((points, rings));
+            }
+
+            let mut capped = vec![[true; 2]; pieces.len()];
+            let mut retry = false;
+            for (node, ends) in active {
+                let rings: Vec<(u32, bool)> = ends.iter().map(|&(idx, side)| (swept[idx].1[side], side == 0)).collect();
+                if mesh.joint(&rings, sides) {
+                    ends.iter().for_each(|&(idx, side)| capped[idx][side] = false);
+                } else {
+                    failed.insert(node);
+                    retry = true;
+                }
+            }
+            if retry {
+                continue;
+            }
+
+            for ((points, rings), [start, end]) in swept.iter().zip(capped) {
+                let last = points.len() - 1;
+                if start {
+                    mesh.cap(points[0].0, (points[0].0 - points[1].0).normalize(), rings[0], sides, true);
+                }
+                if end {
+                    mesh.cap(points[last].0, (points[last].0 - points[last - 1].0).normalize(), rings[1], sides, false);
+                }
+            }
+            return mesh;
+        }
+    }
+
+    /// Splits paths where another path starts, so that pieces only meet at
+    /// their ends, numbering the points where two or more ends meet.
+    fn pieces(paths: &[Vec<Point>]) -> Vec<Piece> {
+        let key = |p: Vec3| p.to_array().map(f32::to_bits);
+        let mut nodes: HashMap<[u32; 3], usize> = HashMap::new();
+        for path in paths.iter() {
+            let node = nodes.len();
+            nodes.entry(key(path[0].0)).or_insert(node);
+        }
+
+        let mut pieces = Vec::new();
+        for path in paths.iter() {
+            let mut start = 0;
+            for i in 1..path.len() {
+                let node = nodes.get(&key(path[i].0)).copied();
+                if node.is_some() || i == path.len() - 1 {
+                    let first = nodes.get(&key(path[start].0)).copied();
+                    pieces.push(Piece { points: path[start..=i].to_vec(), ends: [first, node] });
+                    start = i;
+                }
+            }
+        }
+
+        let mut degrees = vec![0; nodes.len()];
+        for node in pieces.iter().flat_map(|piece| piece.ends).flatten() {
+            degrees[node] += 1;
+        }
+        for end in pieces.iter_mut().flat_map(|piece| piece.ends.iter_mut()) {
+            *end = end.filter(|&node| degrees[node] >= 2);
+        }
+        pieces
+    }
+
+    fn vertex(&mut self, position: Vec3, normal: Vec3, color: [f32; 3]) -> u32 {
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.colors.push(color);
+        self.positions.len() as u32 - 1
+    }
+
+    /// Sweeps an open tube along `points`, returning the first vertex of its
+    /// first and last rings.
+    fn tube(&mut self, points: &[Point], sides: usize) -> [u32; 2] {
+        let dirs: Vec<Vec3> = points.windows(2).map(|w| (w[1].0 - w[0].0).normalize()).collect();
+
+        // a rotation-minimising frame, carried from ring to ring so the tube
+        // doesn't twist
+        let mut normal = dirs[0].any_orthogonal_vector();
+        let mut rings = Vec::with_capacity(points.len());
+        for (i, &(p, r, color)) in points.iter().enumerate() {
+            let (before, after) = (dirs[i.saturating_sub(1)], dirs[i.min(dirs.len() - 1)]);
+            let tangent = (before + after).try_normalize().unwrap_or(after);
+            normal = (normal - tangent * normal.dot(tangent)).try_normalize()
+                .unwrap_or_else(|| tangent.any_orthogonal_vector());
+            let binormal = tangent.cross(normal);
+
+            // stretch the ring along the bend so the tube keeps its thickness
+            let miter = 1.0 / tangent.dot(after).max(0.25);
+            let bend = (after - before).normalize_or_zero();
+            let first = self.positions.len() as u32;
+            for k in 0..sides {
+                let (sin, cos) = (k as f32 / sides as f32 * TAU).sin_cos();
+                let out = normal * cos + binormal * sin;
+                let offset = out + bend * (out.dot(bend) * (miter - 1.0));
+                self.vertex(p + offset * r, out, color);
+            }
+            rings.push(first);
+        }
+
+        for pair in rings.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            for k in 0..sides as u32 {
+                let next = (k + 1) % sides as u32;
+                self.indices.extend([a + k, b + next, b + k, a + k, a + next, b + next]);
+            }
+        }
+        [rings[0], rings[rings.len() - 1]]
+    }
+
+    /// Closes a ring with a fan of triangles around its centre.
+    fn cap(&mut self, centre: Vec3, normal: Vec3, ring: u32, sides: usize, reverse: bool) {
+        let color = self.colors[ring as usize];
+        let middle = self.vertex(centre, normal, color);
+        for k in 0..sides as u32 {
+            let next = (k + 1) % sides as u32;
+            if reverse {
+                self.indices.extend([middle, ring + next, ring + k]);
+            } else {
+                self.indices.extend([middle, ring + k, ring + next]);
+            }
+        }
+    }
+
+    /// Joins tube ends with the convex hull of their rings, less the hull's
+    /// faces across each ring. Rings are given by their first vertex and
+    /// whether they start their tube. Returns whether they could be joined,
+    /// which needs every ring to be a face of the hull.
+    fn joint(&mut self, rings: &[(u32, bool)], sides: usize) -> bool {
+        let vertices: Vec<u32> = rings.iter().flat_map(|&(first, _)| first..first + sides as u32).collect();
+        let points: Vec<DVec3> = vertices.iter().map(|&v| self.positions[v as usize].as_f64()).collect();
+        let Some(faces) = hull(&points) else {
+            return false;
+        };
+        let ring = |i: usize| i / sides;
+        let faces: Vec<[usize; 3]> = faces.into_iter()
+            .filter(|face| ring(face[0]) != ring(face[1]) || ring(face[1]) != ring(face[2]))
+            .collect();
+
+        // what is left must be bounded by exactly the rings, running against
+        // the tubes
+        let edges: HashSet<(usize, usize)> = faces.iter()
+            .flat_map(|&[a, b, c]| [(a, b), (b, c), (c, a)])
+            .collect();
+        let boundary: HashSet<(usize, usize)> = edges.iter().copied().filter(|&(a, b)| !edges.contains(&(b, a))).collect();
+        let expected: HashSet<(usize, usize)> = rings.iter().enumerate()
+            .flat_map(|(idx, &(_, starts))| (0..sides).map(move |k| {
+                let (a, b) = (idx * sides + k, idx * sides + (k + 1) % sides);
+                if starts { (b, a) } else { (a, b) }
+            }))
+            .collect();
+        if edges.len() != faces.len() * 3 || boundary != expected {
+            return false;
+        }
+        self.indices.extend(faces.iter().flatten().map(|&i| vertices[i]));
+        true
+    }
+}
+
+/// How far the tubes meeting at a joint stop short of it so that each end
+/// ring lies on the hull around them all, or `None` if two leave in nearly
+/// the same direction or a tube is too short for it. `ends` are pieces and
+/// which end of them meets there, and `joined` says whether a joint is used.
+fn pull_back(pieces: &[Piece], ends: &[(usize, usize)], joined: impl Fn(&Option<usize>) -> bool) -> Option<f32> {
+    let shapes: Vec<(Vec3, f32, f32)> = ends.iter().map(|&(idx, side)| {
+        let points = &pieces[idx].points;
+        let r = if side == 0 { points[0].1 } else { points[points.len() - 1].1 };
+        // leave room for the other end if that is pulled back too
+        let length: f32 = points.windows(2).map(|w| w[0].0.distance(w[1].0)).sum();
+        let room = if pieces[idx].ends.iter().all(&joined) { 0.45 } else { 0.9 };
+        (pieces[idx].direction(side), r, length * room)
+    }).collect();
+
+    // a ring of radius `r` pulled back by `d` clears the plane of another
+    // ring pulled back as far, at an angle `a` to it, if `d cos a + r sin a`
+    // is less than `d`
+    let mut pull = shapes.iter().map(|&(_, r, _)| r).fold(0.0, f32::max);
+    for (i, &(a, ra, _)) in shapes.iter().enumerate() {
+        for &(b, rb, _) in shapes[i + 1..].iter() {
+            let cos = a.dot(b);
+            if cos > PARALLEL {
+                return None;
+            }
+            let sin = (1.0 - cos * cos).max(0.0).sqrt();
+            pull = pull.max(ra.max(rb) * sin / (1.0 - cos));
+        }
+    }
+    pull *= 1.25;
+    (pull > 0.0 && shapes.iter().all(|&(_, _, room)| pull < room)).then_some(pull)
+}
+
+/// Cuts `length` off the start of `points`, or off the end if `from_end`.
+fn trim(points: &mut Vec<Point>, length: f32, from_end: bool) {
+    if from_end {
+        points.reverse();
+    }
+    let mut left = length;
+    let mut cut = 0;
+    while left > 0.0 && cut + 1 < points.len() {
+        let (a, b) = (points[cut], points[cut + 1]);
+        let step = a.0.distance(b.0);
+        if left < step {
+            let t = left / step;
+            let color = [0, 1, 2].map(|c| a.2[c] + (b.2[c] - a.2[c]) * t);
+            // each point has the radius of the segment that ends there, so
+            // the cut takes the radius of the segment it is on
+            let r = if from_end { a.1 } else { b.1 };
+            points[cut] = (a.0.lerp(b.0, t), r, color);
+            break;
+        }
+        left -= step;
+        cut += 1;
+    }
+    points.drain(..cut);
+    if from_end {
+        points.reverse();
+    }
+}
+
+/// The convex hull of `points` as triangles facing outwards, or `None` if
+/// the points are all in a plane.
+fn hull(points: &[DVec3]) -> Option<Vec<[usize; 3]>> {
+    let centre = points.iter().sum::<DVec3>() / points.len() as f64;
+    let size = points.iter().fold(0.0f64, |size, p| size.max(p.distance(centre)));
+    // Rings are flat, and points in a plane leave it to rounding which of
+    // them the hull passes through. Nudging every point by a little more
+    // than they were rounded by, the same way each time, settles it.
+    let rounding = points.iter().fold(0.0f64, |max, p| max.max(p.abs().max_element())) * f32::EPSILON as f64;
+    let nudge = (size * 1e-5).max(rounding * 4.0);
+    let points: Vec<DVec3> = points.iter().enumerate()
+        .map(|(i, &p)| {
+            let mut hash = (i as u64 + 1).wrapping_mul(0x9E3779B97F4A7C15);
+            let mut next = || {
+                hash ^= hash >> 31;
+                hash = hash.wrapping_mul(0xBF58476D1CE4E5B9);
+                (hash >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+            };
+            p - centre + DVec3::new(next(), next(), next()) * nudge
+        })
+        .collect();
+    let eps = size * 1e-12;
+    let farthest = |measure: &dyn Fn(DVec3) -> f64| {
+        (0..points.len()).max_by(|&i, &j| measure(points[i]).total_cmp(&measure(points[j])))
+    };
+
+    // start from four points far apart, which must be well out of a plane
+    // before nudging
+    let a = 0;
+    let b = farthest(&|p| p.distance(points[a]))?;
+    let c = farthest(&|p| (points[b] - points[a]).cross(p - points[a]).length())?;
+    let normal = (points[b] - points[a]).cross(points[c] - points[a]);
+    let d = farthest(&|p| normal.dot(p - points[a]).abs())?;
+    let height = normal.dot(points[d] - points[a]) / normal.length();
+    if height.is_nan() || height.abs() <= nudge * 10.0 {
+        return None;
+    }
+    let (b, c) = if height > 0.0 { (c, b) } else { (b, c) };
+    let mut faces = vec![[a, b, c], [a, d, b], [b, d, c], [c, d, a]];
+
+    for p in 0..points.len() {
+        if [a, b, c, d].contains(&p) {
+            continue;
+        }
+        let sees = |&[i, j, k]: &[usize; 3]| {
+            let normal = (points[j] - points[i]).cross(points[k] - points[i]);
+            normal.dot(points[p] - points[i]) > eps * normal.length()
+        };
+        let edges: HashSet<(usize, usize)> = faces.iter().filter(|face| sees(face))
+            .flat_map(|&[i, j, k]| [(i, j), (j, k), (k, i)])
+            .collect();
+        if edges.is_empty() {
+            continue;
+        }
+        faces.retain(|face| !sees(face));
+        for &(i, j) in edges.iter() {
+            if !edges.contains(&(j, i)) {
+                faces.push([i, j, p]);
+            }
+        }
+    }
+    Some(faces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsystem::LSystem;
+
+    fn tubes(start: &str, rule: &str, angle: f32, n: u32) -> Mesh {
+        let rules = HashMap::from([("F".to_string(), vec![(rule.to_string(), 1)])]);
+        let geometry = LSystem::new(start, rules, 10.0, angle, 0).draw(n, 1.0);
+        let colors: Vec<Vec<[f32; 3]>> = geometry.sections.iter().map(|section| vec![[1.0; 3]; section.points.len()]).collect();
+        let widths: Vec<Vec<f32>> = geometry.sections.iter()
+            .map(|section| section.segments.iter().map(|segment| segment.width).collect())
+            .collect();
+        Mesh::tubes(&geometry.sections, &colors, &widths, 0.5, 8)
+    }
+
+    /// Whether every edge is shared by exactly two triangles running
+    /// opposite ways, and how many separate closed surfaces there are.
+    fn surfaces(mesh: &Mesh) -> Option<i64> {
+        let mut edges = HashMap::new();
+        for tri in mesh.indices.chunks(3) {
+            for (a, b) in [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])] {
+                *edges.entry((a, b)).or_insert(0) += 1;
+            }
+        }
+        if edges.iter().any(|(&(a, b), &count)| count != 1 || edges.get(&(b, a)) != Some(&1)) {
+            return None;
+        }
+        let vertices = mesh.indices.iter().collect::<HashSet<_>>().len() as i64;
+        let euler = vertices - edges.len() as i64 / 2 + mesh.indices.len() as i64 / 3;
+        Some(euler / 2)
+    }
+
+    fn volume(mesh: &Mesh) -> f32 {
+        mesh.indices.chunks(3)
+            .map(|tri| {
+                let [a, b, c] = [0, 1, 2].map(|i| mesh.positions[tri[i] as usize]);
+                a.dot(b.cross(c)) / 6.0
+            })
+            .sum()
+    }
+
+    #[test]
+    fn branches_join_into_one_closed_surface() {
+        for (rule, angle, n) in [("F[+F]F[-F]F", 25.7, 2), ("F[&F][^F]/F", 30.0, 3), ("F[]F", 20.0, 1), ("F[+F][-F][&F][^F]", 45.0, 1)] {
+            let mesh = tubes("F", rule, angle, n);
+            assert_eq!(surfaces(&mesh), Some(1), "{}", rule);
+            assert!(volume(&mesh) > 0.0, "{}", rule);
+        }
+    }
+
+    #[test]
+    fn overlapping_branches_are_closed_off_apart() {
+        // the second generation's branches start along the first's
+        let mesh = tubes("F", "FF[+F][-F]", 90.0, 2);
+        assert!(surfaces(&mesh).is_some_and(|surfaces| surfaces > 1));
+    }
+
+    #[test]
+    fn shallow_branches_are_closed_off_apart() {
+        // the trunk still joins its continuation, the branch is capped alone
+        let mesh = tubes("F[+F]F", "F", 1.0, 0);
+        assert_eq!(surfaces(&mesh), Some(2));
+        assert!(volume(&mesh) > 0.0);
+    }
+
+    #[test]
+    fn separate_paths_stay_separate() {
+        let mesh = tubes("F+F+FfF", "F", 90.0, 0);
+        assert_eq!(surfaces(&mesh), Some(2));
+    }
+}
@@ -0,0 +1,180 @@
+use std::f32::consts::TAU;
+
+use crate::timeline::Param;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+}
+
+impl Waveform {
+    pub const ALL: [Waveform; 4] = [Waveform::Sine, Waveform::Triangle, Waveform::Saw, Waveform::Square];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Waveform::Sine => "sine",
+            Waveform::Triangle => "triangle",
+            Waveform::Saw => "saw",
+            Waveform::Square => "square",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        Waveform::ALL.into_iter().find(|waveform| waveform.name() == name)
+    }
+
+    /// Samples the waveform at `phase` cycles, returning a value in -1 to 1.
+    fn sample(&self, phase: f32) -> f32 {
+        let phase = phase.rem_euclid(1.0);
+        match self {
+            Waveform::Sine => (phase * TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Saw => 2.0 * phase - 1.0,
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Source {
+    /// Periodic oscillator with `rate` in Hz and `phase` in cycles.
+    Lfo { waveform: Waveform, rate: f32, phase: f32 },
+    /// Smoothly interpolated random values, `rate` new values per second.
+    Noise { rate: f32, seed: u64 },
+    /// Rises from 0 to 1 over `attack` seconds, holds, then falls back over
+    /// `release` seconds, starting at `start`.
+    Envelope { attack: f32, hold: f32, release: f32, looping: bool, start: f32 },
+}
+
+impl Source {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::Lfo { .. } => "lfo",
+            Source::Noise { .. } => "noise",
+            Source::Envelope { .. } => "envelope",
+        }
+    }
+
+    /// LFOs and noise produce values in -1 to 1, envelopes in 0 to 1.
+    pub fn sample(&self, time: f32) -> f32 {
+        match *self {
+            Source::Lfo { waveform, rate, phase } => waveform.sample(time * rate + phase),
+            Source::Noise { rate, seed } => {
+                let x = time * rate;
+                let (a, b) = (noise_at(seed, x.floor() as i64), noise_at(seed, x.floor() as i64 + 1));
+                let t = x.fract();
+                a + (b - a) * t * t * (3.0 - 2.0 * t)
+            }
+            Source::Envelope { attack, hold, release, looping, start } => {
+                let mut t = time - start;
+                let period = attack + hold + release;
+                if looping && period > 0.0 {
+                    t = t.rem_euclid(period);
+                }
+                if t < 0.0 {
+                    0.0
+                } else if t < attack {
+                    t / attack
+                } else if t < attack + hold {
+                    1.0
+                } else if t < period {
+                    1.0 - (t - attack - hold) / release
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Hashes `seed` and `i` to a value in -1 to 1.
+fn noise_at(seed: u64, i: i64) -> f32 {
+    let mut x = seed ^ (i as u64).wrapping_mul(0x9E3779B97F4A7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+    x ^= x >> 31;
+    (x >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+/// Connects a source to a parameter, which is set to `offset + depth * value`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Route {
+    pub source: usize,
+    pub target: Param,
+    pub depth: f32,
+    pub offset: f32,
+    pub enabled: bool,
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Modulation {
+    pub sources: Vec<Source>,
+    pub routes: Vec<Route>,
+}
+
+impl Modulation {
+    /// Removes a source along with the routes that use it.
+    pub fn remove_source(&mut self, idx: usize) {
+        self.sources.remove(idx);
+        self.routes.retain(|route| route.source != idx);
+        for route in self.routes.iter_mut() {
+            if route.source > idx {
+                route.source -= 1;
+            }
+        }
+    }
+
+    /// Restarts all envelopes at `time`.
+    pub fn trigger(&mut self, time: f32) {
+        for source in self.sources.iter_mut() {
+            if let Source::Envelope { start, .. } = source {
+                *start = time;
+            }
+        }
+    }
+
+    /// Returns the modulated value of each routed parameter. Routes to the
+    /// same parameter are summed.
+    pub fn sample(&self, time: f32) -> Vec<(Param, f32)> {
+        let mut values: Vec<(Param, f32)> = Vec::new();
+        for route in self.routes.iter().filter(|route| route.enabled) {
+            let Some(source) = self.sources.get(route.source) else {
+                continue;
+            };
+            let value = route.offset + route.depth * source.sample(time);
+            match values.iter_mut().find(|(param, _)| *param == route.target) {
+                Some((_, total)) => *total += value,
+                None => values.push((route.target, value)),
+            }
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_to_turtle_and_settings_params() {
+        let modulation = Modulation {
+            sources: vec![Source::Lfo { waveform: Waveform::Square, rate: 1.0, phase: 0.0 }],
+            routes: vec![
+                Route { source: 0, target: Param::Step, depth: 0.5, offset: 1.0, enabled: true },
+                Route { source: 0, target: Param::TrailFade, depth: -0.25, offset: 0.5, enabled: true },
+            ],
+        };
+        assert_eq!(modulation.sample(0.25), vec![(Param::Step, 1.5), (Param::TrailFade, 0.25)]);
+        assert_eq!(modulation.sample(0.75), vec![(Param::Step, 0.5), (Param::TrailFade, 0.75)]);
+    }
+
+    #[test]
+    fn params_are_found_by_name() {
+        for param in Param::FIXED.into_iter().chain([Param::RuleWeight(3)]) {
+            assert_eq!(Param::from_name(&param.name()), Some(param));
+        }
+    }
+}
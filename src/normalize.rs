@@ -0,0 +1,30 @@
+/// How the step length shrinks with each generation so the figure keeps its
+/// size as the level changes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Normalize {
+    None,
+    /// Divides the step by how much further each rule carries the turtle
+    /// than the symbol it replaces. Suits curves like Koch's, where drawing
+    /// symbols are rewritten into longer paths.
+    Rules,
+    /// Scales each generation to the size of the first, measured from the
+    /// turtle's bounding box. Suits curves like the dragon, whose drawing
+    /// symbols are never rewritten.
+    Bounds,
+}
+
+impl Normalize {
+    pub const ALL: [Normalize; 3] = [Normalize::None, Normalize::Rules, Normalize::Bounds];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Normalize::None => "none",
+            Normalize::Rules => "rules",
+            Normalize::Bounds => "bounds",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Normalize> {
+        Normalize::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
@@ -0,0 +1,124 @@
+use nannou::glam::{DVec2, Vec2};
+
+use crate::camera::Camera;
+use crate::coloring::Paint;
+use crate::lsystem::{Polygon, Stamp, Stroke};
+use crate::smoothing::Smoothing;
+use crate::surface::{Surface, SurfaceKind};
+
+/// A projected shape with its colour resolved but for the base colour, as
+/// drawn in the view and written by the exporters.
+pub enum Shape {
+    /// A polyline coloured at each point.
+    Line { points: Vec<DVec2>, width: f32, colors: Vec<Paint> },
+    /// Joined cubic Bézier curves, coloured at the start of the first and
+    /// the end of each.
+    Curve { pieces: Vec<[DVec2; 4]>, width: f32, colors: Vec<Paint> },
+    Fill { points: Vec<DVec2>, color: Paint },
+    /// An image centred on `position`, with its x axis along `heading`. The
+    /// length of `heading` is the image's width.
+    Sprite { path: String, position: DVec2, heading: Vec2 },
+}
+
+/// Projects strokes, polygons and stamped surfaces through `camera`, drawing
+/// each kind over the last. Strokes are smoothed into curves by projecting
+/// their control points. Polygons and stamps come with their colours. Stamps
+/// whose symbol has no surface are skipped.
+pub fn shapes<'a>(
+    strokes: Vec<Stroke>,
+    polygons: impl Iterator<Item = (&'a Polygon, Paint)>,
+    stamps: impl Iterator<Item = (&'a Stamp, Paint)>,
+    surfaces: &[Surface],
+    camera: &Camera,
+    smoothing: &Smoothing,
+) -> Vec<Shape> {
+    let mut shapes: Vec<Shape> = strokes.into_iter()
+        .map(|stroke| {
+            let pieces = smoothing.pieces(&stroke.points);
+            if pieces.is_empty() {
+                return Shape::Line {
+                    points: stroke.points.into_iter().map(|p| camera.project(p)).collect(),
+                    width: stroke.width,
+                    colors: stroke.colors,
+                };
+            }
+            let color_at = |location: f32| {
+                let i = (location.floor() as usize).min(stroke.colors.len() - 2);
+                stroke.colors[i].lerp(stroke.colors[i + 1], location - i as f32)
+            };
+            Shape::Curve {
+                colors: std::iter::once(color_at(pieces[0].start))
+                    .chain(pieces.iter().map(|piece| color_at(piece.end)))
+                    .collect(),
+                pieces: pieces.iter().map(|piece| piece.controls.map(|p| camera.project(p))).collect(),
+                width: stroke.width,
+            }
+        })
+        .collect();
+    shapes.extend(polygons.map(|(polygon, color)| Shape::Fill {
+        points: polygon.points.iter().map(|&p| camera.project(p)).collect(),
+        color,
+    }));
+    for (stamp, color) in stamps {
+        let Some(surface) = surfaces.iter().find(|surface| surface.symbol == stamp.symbol) else {
+            continue;
+        };
+        let place = |p: Vec2| stamp.position + ((stamp.heading * p.x + stamp.left * p.y) * stamp.scale).as_f64();
+        shapes.push(match (&surface.kind, surface.outline()) {
+            (_, Some(outline)) => Shape::Fill {
+                points: outline.into_iter().map(|p| camera.project(place(p))).collect(),
+                color,
+            },
+            (SurfaceKind::Sprite(path), None) => {
+                let position = camera.project(stamp.position);
+                Shape::Sprite {
+                    path: path.clone(),
+                    position,
+                    heading: (camera.project(place(Vec2::new(surface.size, 0.0))) - position).as_f32(),
+                }
+            }
+            _ => continue,
+        });
+    }
+    shapes
+}
+
+/// Samples curves into a polyline with `steps` points per piece, along with
+/// the colour at each point.
+pub fn flatten(pieces: &[[DVec2; 4]], colors: &[Paint], steps: usize) -> (Vec<DVec2>, Vec<Paint>) {
+    let mut points = Vec::with_capacity(pieces.len() * steps + 1);
+    let mut point_colors = Vec::with_capacity(pieces.len() * steps + 1);
+    if let (Some(first), Some(&color)) = (pieces.first(), colors.first()) {
+        points.push(first[0]);
+        point_colors.push(color);
+    }
+    for (piece, pair) in pieces.iter().zip(colors.windows(2)) {
+        let [a, b, c, d] = *piece;
+        for step in 1..=steps {
+            let t = step as f64 / steps as f64;
+            let u = 1.0 - t;
+            points.push(a * u * u * u + b * 3.0 * u * u * t + c * 3.0 * u * t * t + d * t * t * t);
+            point_colors.push(pair[0].lerp(pair[1], t as f32));
+        }
+    }
+    (points, point_colors)
+}
+
+/// Points outlining every shape, for fitting the view around them. Curves
+/// are outlined by their control points, and sprites by a square as wide as
+/// they are.
+pub fn extent(shapes: &[Shape]) -> Vec<DVec2> {
+    let mut points = Vec::new();
+    for shape in shapes.iter() {
+        match shape {
+            Shape::Line { points: line, .. } => points.extend(line.iter().copied()),
+            Shape::Curve { pieces, .. } => points.extend(pieces.iter().flatten().copied()),
+            Shape::Fill { points: fill, .. } => points.extend(fill.iter().copied()),
+            Shape::Sprite { position, heading, .. } => {
+                let half = heading.length() as f64 / 2.0;
+                points.extend([(-half, -half), (half, -half), (half, half), (-half, half)].map(|(x, y)| *position + DVec2::new(x, y)));
+            }
+        }
+    }
+    points
+}
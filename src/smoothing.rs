@@ -0,0 +1,128 @@
+use nannou::glam::DVec3;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SmoothMode {
+    None,
+    /// A Catmull-Rom spline through every point.
+    CatmullRom,
+    /// Quadratic curves between the middles of the segments, with the points
+    /// as control points.
+    Bezier,
+    /// Straight segments with each corner rounded off.
+    Round,
+}
+
+impl SmoothMode {
+    pub const ALL: [SmoothMode; 4] = [SmoothMode::None, SmoothMode::CatmullRom, SmoothMode::Bezier, SmoothMode::Round];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SmoothMode::None => "none",
+            SmoothMode::CatmullRom => "catmull-rom",
+            SmoothMode::Bezier => "bezier",
+            SmoothMode::Round => "round",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SmoothMode> {
+        SmoothMode::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Smoothing {
+    pub mode: SmoothMode,
+    /// How far from each corner `Round` starts rounding it. Never more than
+    /// half of either segment.
+    pub radius: f32,
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Smoothing {
+            mode: SmoothMode::None,
+            radius: 5.0,
+        }
+    }
+}
+
+/// A cubic Bézier curve, along with where its ends lie on the polyline it
+/// smooths, as a segment index plus the fraction along that segment.
+pub struct Piece {
+    pub controls: [DVec3; 4],
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Smoothing {
+    /// Fits cubic curves through a polyline. Returns no pieces when smoothing
+    /// is off.
+    pub fn pieces(&self, points: &[DVec3]) -> Vec<Piece> {
+        let n = points.len();
+        if n < 2 {
+            return Vec::new();
+        }
+        match self.mode {
+            SmoothMode::None => Vec::new(),
+            SmoothMode::CatmullRom => (0..n - 1)
+                .map(|i| {
+                    let (a, b) = (points[i], points[i + 1]);
+                    let before = points[i.saturating_sub(1)];
+                    let after = points[(i + 2).min(n - 1)];
+                    Piece {
+                        controls: [a, a + (b - before) / 6.0, b - (after - a) / 6.0, b],
+                        start: i as f32,
+                        end: (i + 1) as f32,
+                    }
+                })
+                .collect(),
+            SmoothMode::Bezier | SmoothMode::Round => {
+                let radius = self.radius as f64;
+                let lengths: Vec<f64> = points.windows(2).map(|w| w[0].distance(w[1])).collect();
+                let along = |i: usize, distance: f64| -> f64 {
+                    if lengths[i] > 0.0 { distance / lengths[i] } else { 0.0 }
+                };
+
+                let mut pieces = Vec::new();
+                // where the previous corner left off, as a point and a location
+                let mut last = (points[0], 0.0);
+                for i in 1..n {
+                    let corner = points[i];
+                    // the fractions of the segments either side of the corner
+                    // where its curve starts and ends
+                    let (start, end) = if i == n - 1 {
+                        (1.0, 0.0)
+                    } else if self.mode == SmoothMode::Bezier {
+                        (0.5, 0.5)
+                    } else {
+                        let cut = radius.min(lengths[i - 1] / 2.0).min(lengths[i] / 2.0);
+                        (1.0 - along(i - 1, cut), along(i, cut))
+                    };
+                    let before = points[i - 1].lerp(corner, start);
+                    if before.distance_squared(last.0) > f64::EPSILON {
+                        pieces.push(line(last, (before, (i - 1) as f32 + start as f32)));
+                    }
+                    if i == n - 1 {
+                        break;
+                    }
+                    let after = corner.lerp(points[i + 1], end);
+                    pieces.push(Piece {
+                        controls: [before, before + (corner - before) * 2.0 / 3.0, after + (corner - after) * 2.0 / 3.0, after],
+                        start: (i - 1) as f32 + start as f32,
+                        end: i as f32 + end as f32,
+                    });
+                    last = (after, i as f32 + end as f32);
+                }
+                pieces
+            }
+        }
+    }
+}
+
+fn line((a, start): (DVec3, f32), (b, end): (DVec3, f32)) -> Piece {
+    Piece {
+        controls: [a, a + (b - a) / 3.0, a + (b - a) * 2.0 / 3.0, b],
+        start,
+        end,
+    }
+}
@@ -0,0 +1,104 @@
+use nannou::glam::Vec2;
+
+/// An axis-aligned box, as its minimum and maximum corners.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Bounds {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Bounds {
+    /// Contains nothing, so that adding any point gives a box around it.
+    pub fn empty() -> Bounds {
+        Bounds { min: Vec2::splat(f32::INFINITY), max: Vec2::splat(f32::NEG_INFINITY) }
+    }
+
+    pub fn around(points: impl IntoIterator<Item = Vec2>) -> Bounds {
+        points.into_iter().fold(Bounds::empty(), |bounds, p| bounds.with(p))
+    }
+
+    pub fn with(self, p: Vec2) -> Bounds {
+        Bounds { min: self.min.min(p), max: self.max.max(p) }
+    }
+
+    pub fn union(self, other: Bounds) -> Bounds {
+        Bounds { min: self.min.min(other.min), max: self.max.max(other.max) }
+    }
+
+    pub fn grow(self, margin: f32) -> Bounds {
+        Bounds { min: self.min - Vec2::splat(margin), max: self.max + Vec2::splat(margin) }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        (self.max - self.min).max(Vec2::ZERO)
+    }
+
+    pub fn centre(&self) -> Vec2 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn overlaps(&self, other: &Bounds) -> bool {
+        self.min.x <= other.max.x && other.min.x <= self.max.x && self.min.y <= other.max.y && other.min.y <= self.max.y
+    }
+}
+
+/// A uniform grid over a set of boxes, for finding those that overlap a
+/// region without testing every one.
+pub struct Grid {
+    bounds: Bounds,
+    cell: f32,
+    columns: usize,
+    rows: usize,
+    /// The boxes overlapping each cell, row by row.
+    cells: Vec<Vec<usize>>,
+}
+
+impl Grid {
+    /// Indexes `items`, aiming for a few boxes per cell.
+    pub fn new(items: &[Bounds]) -> Grid {
+        let bounds = items.iter().fold(Bounds::empty(), |all, item| all.union(*item));
+        let size = bounds.size();
+        let side = (items.len() as f32).sqrt().clamp(1.0, 512.0);
+        let cell = (size.max_element() / side).max(f32::EPSILON);
+        let (columns, rows) = ((size.x / cell) as usize + 1, (size.y / cell) as usize + 1);
+
+        let mut grid = Grid { bounds, cell, columns, rows, cells: vec![Vec::new(); columns * rows] };
+        for (idx, item) in items.iter().enumerate() {
+            if let Some((x, y)) = grid.span(item) {
+                for row in y.clone() {
+                    for column in x.clone() {
+                        grid.cells[row * columns + column].push(idx);
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    /// The columns and rows a box covers, or `None` if it misses the grid.
+    fn span(&self, area: &Bounds) -> Option<(std::ops::Range<usize>, std::ops::Range<usize>)> {
+        if self.cells.is_empty() || !area.overlaps(&self.bounds) {
+            return None;
+        }
+        let cell = |p: Vec2| ((p - self.bounds.min) / self.cell).max(Vec2::ZERO);
+        let (min, max) = (cell(area.min), cell(area.max));
+        let x = min.x as usize..(max.x as usize + 1).min(self.columns);
+        let y = min.y as usize..(max.y as usize + 1).min(self.rows);
+        Some((x, y))
+    }
+
+    /// The indices of the boxes that may overlap `area`, in ascending order.
+    pub fn query(&self, area: &Bounds) -> Vec<usize> {
+        let mut found = Vec::new();
+        if let Some((x, y)) = self.span(area) {
+            for row in y {
+                for column in x.clone() {
+                    found.extend(self.cells[row * self.columns + column].iter().copied());
+                }
+            }
+        }
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+}
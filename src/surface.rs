@@ -0,0 +1,179 @@
+use std::f32::consts::TAU;
+
+use nannou::glam::Vec2;
+
+#[derive(Clone, PartialEq)]
+pub enum SurfaceKind {
+    Circle,
+    Polygon(u32),
+    /// SVG path data, with y pointing down as in an SVG file.
+    Path(String),
+    /// Path to an image file.
+    Sprite(String),
+}
+
+impl SurfaceKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SurfaceKind::Circle => "circle",
+            SurfaceKind::Polygon(_) => "polygon",
+            SurfaceKind::Path(_) => "path",
+            SurfaceKind::Sprite(_) => "sprite",
+        }
+    }
+}
+
+/// A predefined shape stamped by `~` followed by its symbol. Surfaces are laid
+/// out in the turtle's frame, with x along its heading and y to its left, in
+/// units of `size` times the step length. The turtle's position is the
+/// surface's origin.
+#[derive(Clone, PartialEq)]
+pub struct Surface {
+    pub symbol: char,
+    pub kind: SurfaceKind,
+    pub size: f32,
+}
+
+impl Surface {
+    /// The outline of the surface, or `None` for sprites.
+    pub fn outline(&self) -> Option<Vec<Vec2>> {
+        let points = match &self.kind {
+            SurfaceKind::Circle => regular_polygon(32),
+            SurfaceKind::Polygon(sides) => regular_polygon((*sides).max(3)),
+            SurfaceKind::Path(data) => parse_path(data).into_iter().map(|p| Vec2::new(p.x, -p.y)).collect(),
+            SurfaceKind::Sprite(_) => return None,
+        };
+        Some(points.into_iter().map(|p| p * self.size).collect())
+    }
+}
+
+/// A polygon with unit circumradius, with its first vertex along x.
+fn regular_polygon(sides: u32) -> Vec<Vec2> {
+    (0..sides)
+        .map(|k| {
+            let (sin, cos) = (k as f32 / sides as f32 * TAU).sin_cos();
+            Vec2::new(cos, sin)
+        })
+        .collect()
+}
+
+enum Token {
+    Command(char),
+    Number(f32),
+}
+
+fn tokenize(data: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut number = String::new();
+    let flush = |number: &mut String, tokens: &mut Vec<Token>| {
+        if let Ok(n) = number.parse() {
+            tokens.push(Token::Number(n));
+        }
+        number.clear();
+    };
+
+    for c in data.chars() {
+        let numeric = c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E');
+        // numbers can run together, as in `1-2` or `.5.5`
+        let splits = match c {
+            '-' | '+' => !number.ends_with(['e', 'E']),
+            '.' => number.contains('.'),
+            _ => !numeric,
+        };
+        if splits {
+            flush(&mut number, &mut tokens);
+        }
+        if numeric {
+            number.push(c);
+        } else if c.is_ascii_alphabetic() {
+            tokens.push(Token::Command(c));
+        }
+    }
+    flush(&mut number, &mut tokens);
+
+    tokens
+}
+
+/// Flattens SVG path data into a single outline. Supports the `M L H V C Q Z`
+/// commands, absolute and relative, and samples curves at a fixed number of
+/// points. Anything after an unsupported command is ignored.
+pub fn parse_path(data: &str) -> Vec<Vec2> {
+    const CURVE_STEPS: usize = 8;
+
+    let tokens = tokenize(data);
+    let mut points = Vec::new();
+    let mut current = Vec2::ZERO;
+    let mut start = Vec2::ZERO;
+    let mut command = 'M';
+    let mut i = 0;
+    let number = |i: &mut usize| -> Option<f32> {
+        match tokens.get(*i)? {
+            Token::Number(n) => {
+                *i += 1;
+                Some(*n)
+            }
+            Token::Command(_) => None,
+        }
+    };
+
+    while i < tokens.len() {
+        if let Token::Command(c) = tokens[i] {
+            command = c;
+            i += 1;
+        }
+        let relative = command.is_ascii_lowercase();
+        let origin = if relative { current } else { Vec2::ZERO };
+        let point = |i: &mut usize| -> Option<Vec2> { Some(origin + Vec2::new(number(i)?, number(i)?)) };
+
+        match command.to_ascii_uppercase() {
+            'M' | 'L' => {
+                let Some(p) = point(&mut i) else { break };
+                if command.eq_ignore_ascii_case(&'M') {
+                    start = p;
+                    // further pairs after a move are lines
+                    command = if relative { 'l' } else { 'L' };
+                }
+                current = p;
+                points.push(p);
+            }
+            'H' => {
+                let Some(x) = number(&mut i) else { break };
+                current.x = if relative { current.x + x } else { x };
+                points.push(current);
+            }
+            'V' => {
+                let Some(y) = number(&mut i) else { break };
+                current.y = if relative { current.y + y } else { y };
+                points.push(current);
+            }
+            'C' => {
+                let (Some(a), Some(b), Some(end)) = (point(&mut i), point(&mut i), point(&mut i)) else { break };
+                for step in 1..=CURVE_STEPS {
+                    let t = step as f32 / CURVE_STEPS as f32;
+                    let u = 1.0 - t;
+                    points.push(current * u * u * u + a * 3.0 * u * u * t + b * 3.0 * u * t * t + end * t * t * t);
+                }
+                current = end;
+            }
+            'Q' => {
+                let (Some(a), Some(end)) = (point(&mut i), point(&mut i)) else { break };
+                for step in 1..=CURVE_STEPS {
+                    let t = step as f32 / CURVE_STEPS as f32;
+                    let u = 1.0 - t;
+                    points.push(current * u * u + a * 2.0 * u * t + end * t * t);
+                }
+                current = end;
+            }
+            'Z' => {
+                current = start;
+                // numbers must follow a new command
+                if let Some(Token::Number(_)) = tokens.get(i) {
+                    break;
+                }
+            }
+            _ => break,
+        }
+    }
+
+    points
+}
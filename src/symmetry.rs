@@ -0,0 +1,106 @@
+use std::f64::consts::TAU;
+
+use nannou::glam::{DMat3, DVec3};
+
+use crate::coloring::{Colors, Paint};
+use crate::lsystem::Geometry;
+
+/// Copies of the figure arranged around the origin, like a kaleidoscope.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Symmetry {
+    /// Copies spaced evenly around the origin, counting the original.
+    pub copies: u32,
+    /// Adds a reflection of every copy across `axis`.
+    pub mirror: bool,
+    /// Direction of the mirror through the origin, in degrees anticlockwise
+    /// from x.
+    pub axis: f32,
+    /// How far round the colour wheel each copy's colours are turned from
+    /// the one before, in turns.
+    pub hue_shift: f32,
+}
+
+impl Default for Symmetry {
+    fn default() -> Self {
+        Symmetry {
+            copies: 1,
+            mirror: false,
+            axis: 90.0,
+            hue_shift: 0.0,
+        }
+    }
+}
+
+impl Symmetry {
+    pub fn is_none(&self) -> bool {
+        self.copies <= 1 && !self.mirror
+    }
+
+    /// How copy `copy` is placed. The first `copies` are turns about the z
+    /// axis, and any after them are the same turns of the mirror image.
+    fn transform(&self, copy: u32) -> DMat3 {
+        let copies = self.copies.max(1);
+        let turn = DMat3::from_rotation_z(TAU * (copy % copies) as f64 / copies as f64);
+        if copy < copies {
+            return turn;
+        }
+        let (sin, cos) = (2.0 * (self.axis as f64).to_radians()).sin_cos();
+        let reflect = DMat3::from_cols(DVec3::new(cos, sin, 0.0), DVec3::new(sin, -cos, 0.0), DVec3::Z);
+        turn * reflect
+    }
+
+    /// Adds the copies to `geometry`, each tagged with its number so that
+    /// every copy keeps its own branching order and colours.
+    pub fn replicate(&self, mut geometry: Geometry) -> Geometry {
+        if self.is_none() {
+            return geometry;
+        }
+        let count = self.copies.max(1) * if self.mirror { 2 } else { 1 };
+        let (sections, polygons, stamps) = (geometry.sections.clone(), geometry.polygons.clone(), geometry.stamps.clone());
+        for copy in 1..count {
+            let m = self.transform(copy);
+            let m32 = m.as_f32();
+            geometry.sections.extend(sections.iter().map(|section| {
+                let mut section = section.clone();
+                section.points.iter_mut().for_each(|p| *p = m * *p);
+                section.segments.iter_mut().for_each(|segment| segment.copy = copy);
+                section
+            }));
+            geometry.polygons.extend(polygons.iter().map(|polygon| {
+                let mut polygon = polygon.clone();
+                polygon.points.iter_mut().for_each(|p| *p = m * *p);
+                polygon.copy = copy;
+                polygon
+            }));
+            geometry.stamps.extend(stamps.iter().map(|stamp| {
+                let mut stamp = stamp.clone();
+                (stamp.position, stamp.heading, stamp.left) = (m * stamp.position, m32 * stamp.heading, m32 * stamp.left);
+                stamp.copy = copy;
+                stamp
+            }));
+        }
+        geometry
+    }
+
+    /// Turns the hue of each copy's colours by `hue_shift` per copy. Mirror
+    /// images keep the colours of the copy they reflect.
+    pub fn tint(&self, geometry: &Geometry, colors: &mut Colors) {
+        if self.is_none() || self.hue_shift == 0.0 {
+            return;
+        }
+        let shift = |color: &mut Paint, copy: u32| {
+            color.hue_shift += (copy % self.copies.max(1)) as f32 * self.hue_shift;
+        };
+        for (section, colors) in geometry.sections.iter().zip(colors.sections.iter_mut()) {
+            if let Some(copy) = section.segments.first().map(|segment| segment.copy) {
+                colors.iter_mut().for_each(|color| shift(color, copy));
+            }
+        }
+        for (polygon, color) in geometry.polygons.iter().zip(colors.polygons.iter_mut()) {
+            shift(color, polygon.copy);
+        }
+        for (stamp, color) in geometry.stamps.iter().zip(colors.stamps.iter_mut()) {
+            shift(color, stamp.copy);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::lsystem::Geometry;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TaperMode {
+    /// Widths come from the turtle alone.
+    None,
+    /// Narrower with every bracket a segment is nested in.
+    Depth,
+    /// Narrower with every Strahler order a segment is below the trunk's.
+    Order,
+}
+
+impl TaperMode {
+    pub const ALL: [TaperMode; 3] = [TaperMode::None, TaperMode::Depth, TaperMode::Order];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TaperMode::None => "none",
+            TaperMode::Depth => "depth",
+            TaperMode::Order => "order",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TaperMode> {
+        TaperMode::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+/// How stroke width falls off towards the tips. Tapered widths are scaled
+/// by the turtle's width, so `!` and `#` still apply.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Taper {
+    pub mode: TaperMode,
+    /// Width of the trunk.
+    pub base: f32,
+    /// What the width is multiplied by at each level.
+    pub ratio: f32,
+    pub min: f32,
+}
+
+impl Default for Taper {
+    fn default() -> Self {
+        Taper {
+            mode: TaperMode::None,
+            base: 8.0,
+            ratio: 0.7,
+            min: 1.0,
+        }
+    }
+}
+
+impl Taper {
+    /// The width of every segment of every section.
+    pub fn widths(&self, geometry: &Geometry) -> Vec<Vec<f32>> {
+        let sections = &geometry.sections;
+        let levels: Vec<Vec<u32>> = match self.mode {
+            TaperMode::None => return sections.iter()
+                .map(|section| section.segments.iter().map(|segment| segment.width).collect())
+                .collect(),
+            TaperMode::Depth => sections.iter()
+                .map(|section| section.segments.iter().map(|segment| segment.depth).collect())
+                .collect(),
+            TaperMode::Order => {
+                let orders = geometry.strahler_orders();
+                let trunk = orders.iter().flatten().copied().max().unwrap_or(1);
+                orders.into_iter()
+                    .map(|orders| orders.into_iter().map(|order| trunk - order).collect())
+                    .collect()
+            }
+        };
+        sections.iter().zip(levels.iter())
+            .map(|(section, levels)| section.segments.iter().zip(levels.iter())
+                .map(|(segment, &level)| (self.base * self.ratio.powi(level as i32)).max(self.min) * segment.width)
+                .collect())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::lsystem::LSystem;
+
+    fn widths(taper: Taper, command: &str) -> Vec<f32> {
+        let geometry = LSystem::new(command, HashMap::new(), 1.0, 30.0, 0).draw(0, 1.0);
+        taper.widths(&geometry).into_iter().flatten().collect()
+    }
+
+    #[test]
+    fn none_keeps_turtle_widths() {
+        assert_eq!(widths(Taper::default(), "F!(3)F"), vec![1.0, 3.0]);
+    }
+
+    #[test]
+    fn depth_narrows_each_bracket_down_to_min() {
+        let taper = Taper { mode: TaperMode::Depth, base: 8.0, ratio: 0.5, min: 1.5 };
+        assert_eq!(widths(taper, "F[+F[+F[+F]]]"), vec![8.0, 4.0, 2.0, 1.5]);
+        // scaled by the turtle's width
+        assert_eq!(widths(taper, "F[!(2)+F]"), vec![8.0, 8.0]);
+    }
+
+    #[test]
+    fn order_narrows_towards_tips() {
+        let taper = Taper { mode: TaperMode::Order, base: 8.0, ratio: 0.5, min: 0.0 };
+        assert_eq!(widths(taper, "F[+F][-F]"), vec![8.0, 4.0, 4.0]);
+    }
+}
@@ -0,0 +1,278 @@
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    Step,
+}
+
+impl Easing {
+    pub const ALL: [Easing; 5] = [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut, Easing::Step];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Easing::Linear => "linear",
+            Easing::EaseIn => "ease-in",
+            Easing::EaseOut => "ease-out",
+            Easing::EaseInOut => "ease-in-out",
+            Easing::Step => "step",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Easing> {
+        Easing::ALL.into_iter().find(|easing| easing.name() == name)
+    }
+
+    fn apply(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+            Easing::Step => 0.0,
+        }
+    }
+}
+
+/// A numeric parameter that can be animated.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Param {
+    Angle,
+    Length,
+    Rotation,
+    Scale,
+    OffsetX,
+    OffsetY,
+    Level,
+    Hue,
+    Saturation,
+    Brightness,
+    TropismX,
+    TropismY,
+    TropismZ,
+    Susceptibility,
+    /// The turtle's starting heading, step multiple, what `"` and `;`
+    /// multiply by, and how far it jitters.
+    Heading,
+    Step,
+    LengthFactor,
+    AngleFactor,
+    JitterAngle,
+    JitterLength,
+    GrowSpeed,
+    MorphSpeed,
+    TrailFade,
+    TaperBase,
+    TaperRatio,
+    TaperMin,
+    /// Weight of the rule at this index.
+    RuleWeight(usize),
+}
+
+impl Param {
+    pub const FIXED: [Param; 26] = [
+        Param::Angle, Param::Length, Param::Rotation, Param::Scale, Param::OffsetX,
+        Param::OffsetY, Param::Level, Param::Hue, Param::Saturation, Param::Brightness,
+        Param::TropismX, Param::TropismY, Param::TropismZ, Param::Susceptibility,
+        Param::Heading, Param::Step, Param::LengthFactor, Param::AngleFactor,
+        Param::JitterAngle, Param::JitterLength, Param::GrowSpeed, Param::MorphSpeed,
+        Param::TrailFade, Param::TaperBase, Param::TaperRatio, Param::TaperMin,
+    ];
+
+    pub fn name(&self) -> String {
+        match self {
+            Param::Angle => "angle".to_string(),
+            Param::Length => "length".to_string(),
+            Param::Rotation => "rotation".to_string(),
+            Param::Scale => "scale".to_string(),
+            Param::OffsetX => "offset-x".to_string(),
+            Param::OffsetY => "offset-y".to_string(),
+            Param::Level => "level".to_string(),
+            Param::Hue => "hue".to_string(),
+            Param::Saturation => "saturation".to_string(),
+            Param::Brightness => "brightness".to_string(),
+            Param::TropismX => "tropism-x".to_string(),
+            Param::TropismY => "tropism-y".to_string(),
+            Param::TropismZ => "tropism-z".to_string(),
+            Param::Susceptibility => "susceptibility".to_string(),
+            Param::Heading => "heading".to_string(),
+            Param::Step => "step".to_string(),
+            Param::LengthFactor => "length-factor".to_string(),
+            Param::AngleFactor => "angle-factor".to_string(),
+            Param::JitterAngle => "jitter-angle".to_string(),
+            Param::JitterLength => "jitter-length".to_string(),
+            Param::GrowSpeed => "grow-speed".to_string(),
+            Param::MorphSpeed => "morph-speed".to_string(),
+            Param::TrailFade => "trail-fade".to_string(),
+            Param::TaperBase => "taper-base".to_string(),
+            Param::TaperRatio => "taper-ratio".to_string(),
+            Param::TaperMin => "taper-min".to_string(),
+            Param::RuleWeight(idx) => format!("weight:{}", idx),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Param> {
+        if let Some(idx) = name.strip_prefix("weight:") {
+            return idx.parse().ok().map(Param::RuleWeight);
+        }
+        Param::FIXED.into_iter().find(|param| param.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Keyframe {
+    pub time: f32,
+    pub value: f32,
+    /// Easing used on the way from this keyframe to the next.
+    pub easing: Easing,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Track {
+    pub param: Param,
+    keys: Vec<Keyframe>,
+}
+
+impl Track {
+    pub fn new(param: Param) -> Track {
+        Track {
+            param,
+            keys: Vec::new(),
+        }
+    }
+
+    pub fn keys(&self) -> &[Keyframe] {
+        &self.keys
+    }
+
+    /// Adds a keyframe, replacing any existing one at the same time.
+    pub fn insert(&mut self, key: Keyframe) {
+        self.keys.retain(|k| k.time != key.time);
+        let idx = self.keys.partition_point(|k| k.time < key.time);
+        self.keys.insert(idx, key);
+    }
+
+    pub fn remove(&mut self, idx: usize) -> Keyframe {
+        self.keys.remove(idx)
+    }
+
+    pub fn sample(&self, time: f32) -> Option<f32> {
+        let next = self.keys.partition_point(|k| k.time <= time);
+        match (next.checked_sub(1).map(|i| self.keys[i]), self.keys.get(next)) {
+            (None, None) => None,
+            (Some(key), None) => Some(key.value),
+            (None, Some(key)) => Some(key.value),
+            (Some(a), Some(b)) => {
+                let t = a.easing.apply((time - a.time) / (b.time - a.time));
+                Some(a.value + (b.value - a.value) * t)
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Timeline {
+    pub tracks: Vec<Track>,
+    /// Length of the timeline in seconds.
+    pub duration: f32,
+    pub looping: bool,
+    pub time: f32,
+    pub playing: bool,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Timeline {
+            tracks: Vec::new(),
+            duration: 10.0,
+            looping: true,
+            time: 0.0,
+            playing: false,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn track_mut(&mut self, param: Param) -> &mut Track {
+        match self.tracks.iter().position(|track| track.param == param) {
+            Some(idx) => &mut self.tracks[idx],
+            None => {
+                self.tracks.push(Track::new(param));
+                self.tracks.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Moves the playhead forward by `dt` seconds. Returns `false` once a
+    /// non-looping timeline has reached its end.
+    pub fn advance(&mut self, dt: f32) -> bool {
+        self.time += dt;
+        if self.time < self.duration {
+            return true;
+        }
+        if self.looping && self.duration > 0.0 {
+            self.time %= self.duration;
+            true
+        } else {
+            self.time = self.duration;
+            false
+        }
+    }
+
+    pub fn sample(&self) -> Vec<(Param, f32)> {
+        self.tracks.iter()
+            .filter_map(|track| track.sample(self.time).map(|value| (track.param, value)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f32, value: f32, easing: Easing) -> Keyframe {
+        Keyframe { time, value, easing }
+    }
+
+    #[test]
+    fn samples_between_keys_with_easing() {
+        let mut track = Track::new(Param::Angle);
+        assert_eq!(track.sample(0.0), None);
+        track.insert(key(2.0, 10.0, Easing::EaseIn));
+        track.insert(key(0.0, 0.0, Easing::Linear));
+        track.insert(key(4.0, 30.0, Easing::Step));
+        assert_eq!(track.sample(-1.0), Some(0.0));
+        assert_eq!(track.sample(1.0), Some(5.0));
+        assert_eq!(track.sample(3.0), Some(15.0));
+        assert_eq!(track.sample(4.0), Some(30.0));
+        assert_eq!(track.sample(9.0), Some(30.0));
+    }
+
+    #[test]
+    fn step_holds_until_the_next_key() {
+        let mut track = Track::new(Param::Level);
+        track.insert(key(0.0, 1.0, Easing::Step));
+        track.insert(key(1.0, 5.0, Easing::Linear));
+        assert_eq!(track.sample(0.99), Some(1.0));
+        assert_eq!(track.sample(1.0), Some(5.0));
+    }
+
+    #[test]
+    fn insert_replaces_keys_at_the_same_time() {
+        let mut track = Track::new(Param::Hue);
+        track.insert(key(1.0, 1.0, Easing::Linear));
+        track.insert(key(1.0, 2.0, Easing::Linear));
+        assert_eq!(track.keys(), &[key(1.0, 2.0, Easing::Linear)]);
+    }
+
+    #[test]
+    fn advance_loops_or_stops() {
+        let mut timeline = Timeline { duration: 2.0, ..Timeline::default() };
+        assert!(timeline.advance(2.5));
+        assert_eq!(timeline.time, 0.5);
+        timeline.looping = false;
+        assert!(!timeline.advance(2.0));
+        assert_eq!(timeline.time, 2.0);
+    }
+}
@@ -0,0 +1,151 @@
+use nannou::color::{rgb, rgba};
+use nannou::draw::{Draw, Renderer};
+use nannou::prelude::{BLEND_ADD, BLEND_DARKEST, BLEND_LIGHTEST, BLEND_NORMAL};
+use nannou::wgpu;
+
+use crate::canvas;
+use crate::lines::Pass;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum BlendMode {
+    Normal,
+    /// Overlapping lines and trails add up and brighten.
+    Add,
+    /// Keeps the brighter of the figure and what is under it.
+    Lighten,
+    /// Keeps the darker, for light backgrounds.
+    Darken,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 4] = [BlendMode::Normal, BlendMode::Add, BlendMode::Lighten, BlendMode::Darken];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlendMode::Normal => "normal",
+            BlendMode::Add => "add",
+            BlendMode::Lighten => "lighten",
+            BlendMode::Darken => "darken",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<BlendMode> {
+        BlendMode::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    pub fn component(&self) -> wgpu::BlendComponent {
+        match self {
+            BlendMode::Normal => BLEND_NORMAL,
+            BlendMode::Add => BLEND_ADD,
+            BlendMode::Lighten => BLEND_LIGHTEST,
+            BlendMode::Darken => BLEND_DARKEST,
+        }
+    }
+
+    /// A transparent colour that blending over anything leaves it as it was.
+    pub fn blank(&self) -> [f32; 4] {
+        match self {
+            BlendMode::Darken => [1.0, 1.0, 1.0, 0.0],
+            _ => [0.0; 4],
+        }
+    }
+}
+
+/// How earlier frames linger behind the figure.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Trail {
+    /// How much of what was drawn fades into the background each second, from
+    /// 0, where nothing ever fades, to 1, where every frame starts afresh.
+    pub fade: f32,
+    pub background: [f32; 3],
+    pub blend: BlendMode,
+}
+
+impl Default for Trail {
+    fn default() -> Self {
+        Trail {
+            fade: 1.0,
+            background: [0.0; 3],
+            blend: BlendMode::Normal,
+        }
+    }
+}
+
+impl Trail {
+    /// Whether anything is left of the previous frame.
+    pub fn lingers(&self) -> bool {
+        self.fade < 1.0
+    }
+
+    /// How far towards the background a frame lasting `dt` seconds fades.
+    pub fn fade_over(&self, dt: f32) -> f32 {
+        if self.lingers() {
+            1.0 - (1.0 - self.fade.max(0.0)).powf(dt)
+        } else {
+            1.0
+        }
+    }
+}
+
+/// A texture that frames are drawn over rather than replacing, fading a
+/// little towards the background each time. Kept in floating point so that
+/// slow fades reach the background instead of stalling a shade above it.
+pub struct Accumulator {
+    pub texture: wgpu::Texture,
+    size: [u32; 2],
+    draw: Draw,
+    renderer: Renderer,
+    /// Whether nothing has been drawn yet.
+    empty: bool,
+}
+
+impl Accumulator {
+    pub fn new(device: &wgpu::Device, size: [u32; 2]) -> Accumulator {
+        let (texture, renderer) = canvas::offscreen(device, size);
+        Accumulator {
+            texture,
+            size,
+            draw: Draw::new(),
+            renderer,
+            empty: true,
+        }
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Fades what is there by a frame lasting `dt` seconds, then has `figure`
+    /// draw over it with the trail's blend mode, either by encoding a pass of
+    /// lines or into the draw, which goes over the lines. `base` fills in the
+    /// base colour of the lines.
+    pub fn step(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        trail: &Trail,
+        base: [f32; 3],
+        dt: f32,
+        figure: impl FnOnce(&Draw, &mut wgpu::CommandEncoder, &Pass),
+    ) {
+        let [r, g, b] = trail.background;
+        self.draw.reset();
+        if self.empty {
+            self.draw.background().color(rgb(r, g, b));
+            self.empty = false;
+        } else {
+            self.draw.rect()
+                .w_h(self.size[0] as f32, self.size[1] as f32)
+                .color(rgba(r, g, b, trail.fade_over(dt)));
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("trail") });
+        self.renderer.render_to_texture(device, &mut encoder, &self.draw, &self.texture);
+
+        self.draw.reset();
+        let target = self.texture.view().build();
+        let pass = Pass { device, target: &target, size: self.size, samples: 1, blend: trail.blend, clear: None, base };
+        figure(&self.draw.color_blend(trail.blend.component()), &mut encoder, &pass);
+        self.renderer.render_to_texture(device, &mut encoder, &self.draw, &self.texture);
+        queue.submit(Some(encoder.finish()));
+    }
+}
@@ -1,64 +1,217 @@
-use nannou::{geom::pt2, glam::Vec2};
+use nannou::glam::{DQuat, DVec3, Vec3};
 pub struct State {
-    pos: Vec2,
-    head: f32,
+    pos: DVec3,
+    head: DVec3,
+    left: DVec3,
+    up: DVec3,
+    angle: Option<f64>,
+    width: f32,
+    color: usize,
+    step_scale: f32,
+    angle_scale: f32,
 }
 
+/// A 3D turtle oriented by its heading, left and up vectors. It starts in the
+/// xy plane heading along x, so turning left and right alone draws exactly the
+/// same figures as a 2D turtle. Positions are kept in double precision so
+/// that long paths don't drift.
 pub struct Turtle {
     stack: Vec<State>,
-    position: Vec2,
-    heading: f32, // radians
+    position: DVec3,
+    heading: DVec3,
+    left: DVec3,
+    up: DVec3,
+    /// The heading in degrees anticlockwise from x, while the turtle has only
+    /// turned within the xy plane. Turns are added up exactly and the heading
+    /// worked out afresh from the total, so rounding never builds up.
+    angle: Option<f64>,
+    width: f32,
+    color: usize,
+    /// Multiplies every step, changed by `"`.
+    step_scale: f32,
+    /// Multiplies every turn, changed by `;`.
+    angle_scale: f32,
+}
+
+/// Rotates `a` towards `b` by `degrees` within the plane they span.
+fn rotate(a: DVec3, b: DVec3, degrees: f32) -> (DVec3, DVec3) {
+    let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+    (a * cos + b * sin, b * cos - a * sin)
+}
+
+/// The sine and cosine of `degrees`, exact at multiples of 90°.
+fn sin_cos(degrees: f64) -> (f64, f64) {
+    let quarters = degrees / 90.0;
+    if quarters.fract() != 0.0 {
+        return degrees.to_radians().sin_cos();
+    }
+    match quarters.rem_euclid(4.0) as u32 {
+        0 => (0.0, 1.0),
+        1 => (1.0, 0.0),
+        2 => (0.0, -1.0),
+        _ => (-1.0, 0.0),
+    }
 }
 
 impl Turtle {
     pub fn new() -> Turtle {
-        let pos = pt2(0.0, 0.0);
-
         Turtle {
-            position: pos,
+            position: DVec3::ZERO,
             stack: Vec::new(),
-            heading: 0.0,
+            heading: DVec3::X,
+            left: DVec3::Y,
+            up: DVec3::Z,
+            angle: Some(0.0),
+            width: 1.0,
+            color: 0,
+            step_scale: 1.0,
+            angle_scale: 1.0,
         }
     }
 
-    pub fn curr(&self) -> Vec2 {
+    /// Moves the turtle without drawing.
+    pub fn jump(&mut self, position: DVec3) {
+        self.position = position;
+    }
+
+    pub fn curr(&self) -> DVec3 {
         self.position
     }
 
-    pub fn fd(&mut self, distance: f32) -> Vec2 {
-        let new_pos = pt2(
-            self.position.x + (distance * self.heading.cos()),
-            self.position.y + (distance * self.heading.sin())
-        );
+    /// The heading and left vectors.
+    pub fn orientation(&self) -> (Vec3, Vec3) {
+        (self.heading.as_f32(), self.left.as_f32())
+    }
+
+    pub fn width(&self) -> f32 {
+        self.width
+    }
+
+    pub fn set_width(&mut self, width: f32) {
+        self.width = width;
+    }
+
+    pub fn color(&self) -> usize {
+        self.color
+    }
+
+    pub fn set_color(&mut self, color: usize) {
+        self.color = color;
+    }
+
+    pub fn next_color(&mut self) {
+        self.color += 1;
+    }
+
+    pub fn step_scale(&self) -> f32 {
+        self.step_scale
+    }
+
+    pub fn scale_step(&mut self, factor: f32) {
+        self.step_scale *= factor;
+    }
+
+    pub fn angle_scale(&self) -> f32 {
+        self.angle_scale
+    }
+
+    pub fn scale_angle(&mut self, factor: f32) {
+        self.angle_scale *= factor;
+    }
+
+    pub fn fd(&mut self, distance: f32) -> DVec3 {
+        let new_pos = self.position + self.heading * distance as f64;
         self.position = new_pos;
         new_pos
     }
 
     pub fn left(&mut self, degrees: f32) {
-        self.heading = self.heading + degrees.to_radians();
+        match self.angle.as_mut() {
+            Some(angle) => {
+                *angle = (*angle + degrees as f64).rem_euclid(360.0);
+                let (sin, cos) = sin_cos(*angle);
+                self.heading = DVec3::new(cos, sin, 0.0);
+                self.left = DVec3::new(-sin, cos, 0.0);
+            }
+            None => (self.heading, self.left) = rotate(self.heading, self.left, degrees),
+        }
     }
 
     pub fn right(&mut self, degrees: f32) {
-        self.heading = self.heading - degrees.to_radians();
+        self.left(-degrees);
+    }
+
+    pub fn pitch_down(&mut self, degrees: f32) {
+        self.angle = None;
+        (self.heading, self.up) = rotate(self.heading, self.up, -degrees);
+    }
+
+    pub fn pitch_up(&mut self, degrees: f32) {
+        self.pitch_down(-degrees);
+    }
+
+    pub fn roll_left(&mut self, degrees: f32) {
+        self.angle = None;
+        (self.left, self.up) = rotate(self.left, self.up, degrees);
+    }
+
+    pub fn roll_right(&mut self, degrees: f32) {
+        self.roll_left(-degrees);
+    }
+
+    /// Turns towards `tropism` by `susceptibility` times |H × T| radians, so
+    /// the pull is strongest when the turtle is heading across it.
+    pub fn bend(&mut self, tropism: Vec3, susceptibility: f32) {
+        let axis = self.heading.cross(tropism.as_f64());
+        let angle = susceptibility as f64 * axis.length();
+        let Some(axis) = axis.try_normalize() else {
+            return;
+        };
+        self.angle = None;
+        let rotation = DQuat::from_axis_angle(axis, angle);
+        self.heading = (rotation * self.heading).normalize();
+        self.left = (rotation * self.left).normalize();
+        self.up = (rotation * self.up).normalize();
+    }
+
+    pub fn turn_around(&mut self) {
+        match self.angle {
+            Some(_) => self.left(180.0),
+            None => (self.heading, self.left) = (-self.heading, -self.left),
+        }
     }
 
     pub fn push(&mut self) {
         self.stack.push(State {
             pos: self.position,
-            head: self.heading
+            head: self.heading,
+            left: self.left,
+            up: self.up,
+            angle: self.angle,
+            width: self.width,
+            color: self.color,
+            step_scale: self.step_scale,
+            angle_scale: self.angle_scale,
         });
     }
 
-    pub fn pop(&mut self) -> Result<Vec2, &str> {
+    pub fn pop(&mut self) -> Result<DVec3, &str> {
         let last = self.stack.pop();
         match last {
-            Some(State { pos, head }) => {
+            Some(State { pos, head, left, up, angle, width, color, step_scale, angle_scale }) => {
                 self.position = pos;
                 self.heading = head;
+                self.left = left;
+                self.up = up;
+                self.angle = angle;
+                self.width = width;
+                self.color = color;
+                self.step_scale = step_scale;
+                self.angle_scale = angle_scale;
 
                 Ok(self.position)
             },
             None => Err("Malformed command: nothing to pop!"),
         }
     }
-}
\ No newline at end of file
+}